@@ -0,0 +1,252 @@
+use crate::{Backup, BackupMethod, InPlace, InPlaceError, Output, SavedFile};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::Path;
+
+/// A builder for atomically replacing a file's contents without reading it.
+///
+/// `AtomicWrite` is like [`InPlace`] minus the reader: it supports the same
+/// backup, symlink, temporary file, and permission options, but the edited
+/// file is never opened for reading, and so it can be used on files that the
+/// current user is not allowed to read.  Data written to the resulting
+/// [`AtomicWriteFile`] only replaces the edited file once
+/// [`AtomicWriteFile::commit()`] is called.
+///
+/// Unlike `InPlace`, `AtomicWrite` defaults to creating the edited file if it
+/// does not exist; see [`AtomicWrite::create()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AtomicWrite(InPlace);
+
+impl AtomicWrite {
+    /// Create a new `AtomicWrite` instance for replacing the given path
+    /// (hereafter called the "edited path").
+    pub fn new<P: AsRef<Path>>(path: P) -> AtomicWrite {
+        let mut inner = InPlace::new(path);
+        inner.create(true);
+        AtomicWrite(inner)
+    }
+
+    /// Move the edited file to the path given by `backup` when
+    /// [`AtomicWriteFile::commit()`] is called.  See [`InPlace::backup()`]
+    /// for more information.
+    pub fn backup(&mut self, backup: Backup) -> &mut Self {
+        self.0.backup(backup);
+        self
+    }
+
+    /// Do not move the edited file to a backup path.  This is the default
+    /// behavior.
+    ///
+    /// This overrides any previous calls to [`AtomicWrite::backup()`].
+    pub fn no_backup(&mut self) -> &mut Self {
+        self.0.no_backup();
+        self
+    }
+
+    /// Set how the edited file is backed up when
+    /// [`AtomicWriteFile::commit()`] is called.  See
+    /// [`InPlace::backup_method()`] for more information.
+    pub fn backup_method(&mut self, method: BackupMethod) -> &mut Self {
+        self.0.backup_method(method);
+        self
+    }
+
+    /// Compress the backup of the edited file with the given format.  See
+    /// [`InPlace::compress_backup()`] for more information.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+    pub fn compress_backup(&mut self, compression: crate::Compression) -> &mut Self {
+        self.0.compress_backup(compression);
+        self
+    }
+
+    /// Set whether to resolve symlinks in the edited path.  See
+    /// [`InPlace::follow_symlinks()`] for more information.
+    pub fn follow_symlinks(&mut self, flag: bool) -> &mut Self {
+        self.0.follow_symlinks(flag);
+        self
+    }
+
+    /// Set the prefix of the temporary file's filename.  See
+    /// [`InPlace::temp_prefix()`] for more information.
+    pub fn temp_prefix<S: Into<OsString>>(&mut self, prefix: S) -> &mut Self {
+        self.0.temp_prefix(prefix);
+        self
+    }
+
+    /// Set the suffix of the temporary file's filename.  See
+    /// [`InPlace::temp_suffix()`] for more information.
+    pub fn temp_suffix<S: Into<OsString>>(&mut self, suffix: S) -> &mut Self {
+        self.0.temp_suffix(suffix);
+        self
+    }
+
+    /// Set the number of random characters in the temporary file's filename.
+    /// See [`InPlace::temp_rand_len()`] for more information.
+    pub fn temp_rand_len(&mut self, len: usize) -> &mut Self {
+        self.0.temp_rand_len(len);
+        self
+    }
+
+    /// Set the prefix and suffix of the temporary file's filename from a
+    /// template.  See [`InPlace::temp_template()`] for more information.
+    pub fn temp_template(&mut self, template: &str) -> &mut Self {
+        self.0.temp_template(template);
+        self
+    }
+
+    /// Set whether to delay copying the edited file's permissions to the
+    /// temporary file until [`AtomicWriteFile::commit()`] is called.  See
+    /// [`InPlace::defer_permissions()`] for more information.
+    pub fn defer_permissions(&mut self, flag: bool) -> &mut Self {
+        self.0.defer_permissions(flag);
+        self
+    }
+
+    /// Give the edited file the given permission bits instead of copying the
+    /// permissions of the original file.  See [`InPlace::mode()`] for more
+    /// information.
+    #[cfg(unix)]
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.0.mode(mode);
+        self
+    }
+
+    /// Set whether to create the edited file if it does not exist.  Unlike
+    /// with [`InPlace::create()`], the default is true.  If `flag` is false,
+    /// [`AtomicWrite::open()`] will fail if the edited path does not exist.
+    pub fn create(&mut self, flag: bool) -> &mut Self {
+        self.0.create(flag);
+        self
+    }
+
+    /// Require the edited path to not exist.  See [`InPlace::create_new()`]
+    /// for more information.
+    pub fn create_new(&mut self, flag: bool) -> &mut Self {
+        self.0.create_new(flag);
+        self
+    }
+
+    /// Set the permission bits to give the edited file if it is created.  See
+    /// [`InPlace::default_mode()`] for more information.
+    #[cfg(unix)]
+    pub fn default_mode(&mut self, mode: u32) -> &mut Self {
+        self.0.default_mode(mode);
+        self
+    }
+
+    /// Run `func` on the path to the temporary file when
+    /// [`AtomicWriteFile::commit()`] is called, before the edited file is
+    /// backed up or replaced.  See [`InPlace::validate()`] for more
+    /// information.
+    pub fn validate<F, E>(&mut self, func: F) -> &mut Self
+    where
+        F: Fn(&Path) -> Result<(), E> + Send + Sync + 'static,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        self.0.validate(func);
+        self
+    }
+
+    /// Create a temporary file for writing the new contents of the edited
+    /// path.
+    ///
+    /// This performs the same operations as [`InPlace::open()`], except that
+    /// the edited path is not opened for reading.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn open(&self) -> Result<AtomicWriteFile, InPlaceError> {
+        self.0.open_output().map(AtomicWriteFile)
+    }
+}
+
+/// A file whose replacement contents are currently being written.
+///
+/// An `AtomicWriteFile` instance can be obtained via [`AtomicWrite::open()`].
+///
+/// In order to replace the edited file with the written bytes,
+/// [`AtomicWriteFile::commit()`] must be called once writing is complete.
+/// Alternatively, calling [`AtomicWriteFile::abort()`] will discard all
+/// written bytes and leave the edited file unmodified.
+///
+/// Dropping an `AtomicWriteFile` without calling `commit()` has the same
+/// effect as calling `abort()`, except that any errors are ignored.
+#[derive(Debug)]
+pub struct AtomicWriteFile(Output);
+
+impl AtomicWriteFile {
+    /// The writer file handle
+    pub fn writer(&self) -> &File {
+        self.0.writer()
+    }
+
+    /// The path to the edited file.  If `follow_symlinks` was set to `true`,
+    /// this will be a canonical path; otherwise, the path is only guaranteed
+    /// to be absolute.
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// The path to the temporary file that the writer file handle writes to.
+    /// This is an absolute path in the same directory as the edited file.
+    pub fn temp_path(&self) -> &Path {
+        self.0.temp_path()
+    }
+
+    /// The path, if any, where the edited file will be backed up once
+    /// [`AtomicWriteFile::commit()`] is called.  This is an absolute path.
+    pub fn backup_path(&self) -> Option<&Path> {
+        self.0.backup_path()
+    }
+
+    /// Save the edited file at the backup path, if any, and replace it with
+    /// the temporary output file.
+    ///
+    /// This performs the same operations as [`InPlaceFile::save()`].
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn commit(self) -> Result<(), InPlaceError> {
+        self.0.save()
+    }
+
+    /// Commit the new contents as with [`AtomicWriteFile::commit()`], and
+    /// return a [`SavedFile`] that can be used to undo the replacement
+    /// afterwards.  See [`InPlaceFile::save_revertible()`] for more
+    /// information.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save_revertible()`]: crate::InPlaceFile::save_revertible
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn commit_revertible(self) -> Result<SavedFile, InPlaceError> {
+        self.0.save_revertible()
+    }
+
+    /// Close the writer file handle and do not update or back up the edited
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn abort(self) -> Result<(), InPlaceError> {
+        self.0.discard()
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{Backup, InPlace, InPlaceError};
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use rayon::prelude::*;
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// A builder for editing every file in a directory tree in parallel.
+///
+/// Files are found by walking a root directory, filtered by glob patterns and
+/// `.gitignore`-style ignore files, and then edited in parallel using
+/// [`rayon`].  Each file is opened with the settings of an [`InPlace`]
+/// template, read into memory, and passed to a transformation function; if
+/// the function's output differs from the file's contents, the file is saved
+/// with the new contents, and otherwise it is left untouched.
+#[cfg_attr(docsrs, doc(cfg(feature = "bulk")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BulkEdit {
+    root: PathBuf,
+    globs: Vec<String>,
+    template: InPlace,
+    git_ignore: bool,
+    hidden: bool,
+}
+
+impl BulkEdit {
+    /// Create a new `BulkEdit` instance for editing files under the given
+    /// directory
+    pub fn new<P: AsRef<Path>>(root: P) -> BulkEdit {
+        BulkEdit {
+            root: root.as_ref().into(),
+            globs: Vec::new(),
+            template: InPlace::new(""),
+            git_ignore: true,
+            hidden: false,
+        }
+    }
+
+    /// Add a glob pattern for selecting files to edit.  Patterns use
+    /// `.gitignore` syntax and are matched relative to the root directory; a
+    /// pattern starting with `!` excludes matching files instead.
+    ///
+    /// If no patterns are added, all files are selected.  If any non-`!`
+    /// patterns are added, only files matching at least one of them are
+    /// selected.
+    pub fn glob<S: Into<String>>(&mut self, pattern: S) -> &mut Self {
+        self.globs.push(pattern.into());
+        self
+    }
+
+    /// Set the [`InPlace`] whose settings (backup, symlink handling, etc.)
+    /// are used to open each file.  The path that `template` was constructed
+    /// with is ignored.
+    ///
+    /// As every file would be backed up to the same path, a backup specifier
+    /// of [`Backup::Path`] is not supported; if one is set, every file fails
+    /// without being opened with an error of kind
+    /// [`InPlaceErrorKind::SharedBackup`].
+    ///
+    /// [`InPlaceErrorKind::SharedBackup`]: crate::InPlaceErrorKind::SharedBackup
+    pub fn template(&mut self, template: InPlace) -> &mut Self {
+        self.template = template;
+        self
+    }
+
+    /// If `flag` is true (the default), files ignored by `.gitignore`,
+    /// `.ignore`, and similar files are not edited.  Unlike `git`, this does
+    /// not require the root to be inside a Git repository.
+    pub fn git_ignore(&mut self, flag: bool) -> &mut Self {
+        self.git_ignore = flag;
+        self
+    }
+
+    /// If `flag` is true, hidden files & directories are also edited.  The
+    /// default is false.
+    pub fn hidden(&mut self, flag: bool) -> &mut Self {
+        self.hidden = flag;
+        self
+    }
+
+    /// Walk the root directory and edit each selected file in parallel by
+    /// passing its path & contents to `transform`.  If `transform` returns
+    /// new contents that differ from the original contents, the file is saved
+    /// with the new contents; if the contents are the same, the file is
+    /// discarded without being modified.  If `transform` returns an error,
+    /// the file is discarded.
+    ///
+    /// Failures for individual files do not stop other files from being
+    /// edited; they are instead recorded in the returned [`BulkReport`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the glob patterns is invalid.
+    pub fn run<F, E>(&self, transform: F) -> Result<BulkReport<E>, ignore::Error>
+    where
+        F: Fn(&Path, &[u8]) -> Result<Vec<u8>, E> + Sync,
+        E: Send,
+    {
+        let mut overrides = OverrideBuilder::new(&self.root);
+        for pattern in &self.globs {
+            overrides.add(pattern)?;
+        }
+        let overrides = overrides.build()?;
+        // The globs are matched against files after walking rather than being
+        // passed to `WalkBuilder::overrides()`, as overrides take precedence
+        // over the hidden & ignore file filters.
+        let walker = WalkBuilder::new(&self.root)
+            .standard_filters(self.git_ignore)
+            .hidden(!self.hidden)
+            .require_git(false)
+            .build();
+        let mut files = Vec::new();
+        let mut report = BulkReport {
+            changed: Vec::new(),
+            unchanged: Vec::new(),
+            failed: Vec::new(),
+        };
+        for entry in walker {
+            match entry {
+                Ok(entry)
+                    if entry.file_type().is_some_and(|ft| ft.is_file())
+                        && !overrides.matched(entry.path(), false).is_ignore() =>
+                {
+                    files.push(entry.into_path());
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    let path = walk_error_path(&e).unwrap_or_else(|| self.root.clone());
+                    report.failed.push((path, BulkError::Walk(e)));
+                }
+            }
+        }
+        let outcomes = files
+            .into_par_iter()
+            .map(|path| {
+                let r = self.edit(&path, &transform);
+                (path, r)
+            })
+            .collect::<Vec<_>>();
+        for (path, r) in outcomes {
+            match r {
+                Ok(true) => report.changed.push(path),
+                Ok(false) => report.unchanged.push(path),
+                Err(e) => report.failed.push((path, e)),
+            }
+        }
+        report.changed.sort();
+        report.unchanged.sort();
+        report.failed.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+        Ok(report)
+    }
+
+    /// Edit a single file, returning whether it was changed
+    fn edit<F, E>(&self, path: &Path, transform: &F) -> Result<bool, BulkError<E>>
+    where
+        F: Fn(&Path, &[u8]) -> Result<Vec<u8>, E>,
+    {
+        if matches!(self.template.backup, Some(Backup::Path(_))) {
+            return Err(BulkError::InPlace(InPlaceError::shared_backup()));
+        }
+        let mut inp = self.template.clone();
+        inp.path = path.into();
+        let inp = inp.open().map_err(BulkError::InPlace)?;
+        let mut content = Vec::new();
+        if let Err(e) = inp.reader().read_to_end(&mut content) {
+            let _ = inp.discard();
+            return Err(BulkError::Read(e));
+        }
+        let new_content = match transform(path, &content) {
+            Ok(c) => c,
+            Err(e) => {
+                let _ = inp.discard();
+                return Err(BulkError::Transform(e));
+            }
+        };
+        if new_content == content {
+            inp.discard().map_err(BulkError::InPlace)?;
+            return Ok(false);
+        }
+        if let Err(e) = inp.writer().write_all(&new_content) {
+            let _ = inp.discard();
+            return Err(BulkError::Write(e));
+        }
+        inp.save().map_err(BulkError::InPlace)?;
+        Ok(true)
+    }
+}
+
+/// Extract the path, if any, that a directory-walking error is about
+fn walk_error_path(e: &ignore::Error) -> Option<PathBuf> {
+    match e {
+        ignore::Error::WithPath { path, .. } => Some(path.clone()),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            walk_error_path(err)
+        }
+        ignore::Error::Loop { child, .. } => Some(child.clone()),
+        _ => None,
+    }
+}
+
+/// The results of a call to [`BulkEdit::run()`]
+#[cfg_attr(docsrs, doc(cfg(feature = "bulk")))]
+#[derive(Debug)]
+pub struct BulkReport<E> {
+    changed: Vec<PathBuf>,
+    unchanged: Vec<PathBuf>,
+    failed: Vec<(PathBuf, BulkError<E>)>,
+}
+
+impl<E> BulkReport<E> {
+    /// The files that were modified, in sorted order
+    pub fn changed(&self) -> &[PathBuf] {
+        &self.changed
+    }
+
+    /// The files that were left unmodified because the transformation did
+    /// not change their contents, in sorted order
+    pub fn unchanged(&self) -> &[PathBuf] {
+        &self.unchanged
+    }
+
+    /// The files (or, for errors that occurred while walking the directory
+    /// tree, the directories) for which an error occurred, in sorted order
+    pub fn failed(&self) -> &[(PathBuf, BulkError<E>)] {
+        &self.failed
+    }
+
+    /// Returns true if no errors occurred
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// An error that occurred while processing a single file with
+/// [`BulkEdit::run()`]
+#[cfg_attr(docsrs, doc(cfg(feature = "bulk")))]
+#[derive(Debug)]
+pub enum BulkError<E> {
+    /// An error occurred while walking the directory tree
+    Walk(ignore::Error),
+
+    /// Opening, saving, or discarding the file failed
+    InPlace(InPlaceError),
+
+    /// Reading the file's contents failed
+    Read(io::Error),
+
+    /// The transformation function returned an error
+    Transform(E),
+
+    /// Writing the new contents to the temporary file failed
+    Write(io::Error),
+}
+
+impl<E: fmt::Display> fmt::Display for BulkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkError::Walk(_) => write!(f, "failed to walk directory"),
+            BulkError::InPlace(e) => write!(f, "{e}"),
+            BulkError::Read(_) => write!(f, "failed to read file"),
+            BulkError::Transform(e) => write!(f, "{e}"),
+            BulkError::Write(_) => write!(f, "failed to write to temporary file"),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for BulkError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BulkError::Walk(e) => Some(e),
+            BulkError::InPlace(e) => e.source(),
+            BulkError::Read(e) | BulkError::Write(e) => Some(e),
+            BulkError::Transform(e) => e.source(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+use super::{BulkEdit, BulkError};
+use crate::{Backup, InPlace, InPlaceErrorKind};
+use assert_fs::fixture::TempDir;
+use assert_fs::prelude::*;
+use std::path::PathBuf;
+
+#[expect(clippy::unnecessary_wraps)]
+fn upcase(_: &std::path::Path, content: &[u8]) -> Result<Vec<u8>, String> {
+    Ok(content.to_ascii_uppercase())
+}
+
+#[test]
+fn edit_tree() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir.child("a.txt").write_str("foo\n").unwrap();
+    tmpdir.child("b.txt").write_str("BAR\n").unwrap();
+    tmpdir.child("sub/c.txt").write_str("baz\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root).run(upcase).unwrap();
+    assert!(report.is_success());
+    assert_eq!(
+        report.changed(),
+        [root.join("a.txt"), root.join("sub").join("c.txt")]
+    );
+    assert_eq!(report.unchanged(), [root.join("b.txt")]);
+    tmpdir.child("a.txt").assert("FOO\n");
+    tmpdir.child("b.txt").assert("BAR\n");
+    tmpdir.child("sub/c.txt").assert("BAZ\n");
+}
+
+#[test]
+fn globs_and_gitignore() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir
+        .child(".gitignore")
+        .write_str("ignored/\nignored.txt\n")
+        .unwrap();
+    tmpdir.child("a.txt").write_str("foo\n").unwrap();
+    tmpdir.child("a.md").write_str("foo\n").unwrap();
+    tmpdir.child("skip.txt").write_str("foo\n").unwrap();
+    tmpdir.child("ignored/b.txt").write_str("foo\n").unwrap();
+    tmpdir.child(".hidden.txt").write_str("foo\n").unwrap();
+    tmpdir.child("ignored.txt").write_str("foo\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root)
+        .glob("*.txt")
+        .glob("!skip.txt")
+        .run(upcase)
+        .unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.changed(), [root.join("a.txt")]);
+    assert!(report.unchanged().is_empty());
+    tmpdir.child("a.txt").assert("FOO\n");
+    tmpdir.child("a.md").assert("foo\n");
+    tmpdir.child("skip.txt").assert("foo\n");
+    tmpdir.child("ignored/b.txt").assert("foo\n");
+    tmpdir.child(".hidden.txt").assert("foo\n");
+    tmpdir.child("ignored.txt").assert("foo\n");
+}
+
+#[test]
+fn no_gitignore_hidden() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir.child(".gitignore").write_str("ignored/\n").unwrap();
+    tmpdir.child("ignored/b.txt").write_str("foo\n").unwrap();
+    tmpdir.child(".hidden.txt").write_str("foo\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root)
+        .glob("*.txt")
+        .git_ignore(false)
+        .hidden(true)
+        .run(upcase)
+        .unwrap();
+    assert!(report.is_success());
+    assert_eq!(
+        report.changed(),
+        [root.join(".hidden.txt"), root.join("ignored").join("b.txt")]
+    );
+    tmpdir.child("ignored/b.txt").assert("FOO\n");
+    tmpdir.child(".hidden.txt").assert("FOO\n");
+}
+
+#[test]
+fn template_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir.child("a.txt").write_str("foo\n").unwrap();
+    tmpdir.child("b.txt").write_str("BAR\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root)
+        .glob("*.txt")
+        .template(
+            InPlace::new("")
+                .backup(Backup::Append(".bak".into()))
+                .clone(),
+        )
+        .run(upcase)
+        .unwrap();
+    assert!(report.is_success());
+    tmpdir.child("a.txt").assert("FOO\n");
+    tmpdir.child("a.txt.bak").assert("foo\n");
+    tmpdir.child("b.txt").assert("BAR\n");
+    assert!(!tmpdir.child("b.txt.bak").exists());
+}
+
+#[test]
+fn template_backup_path() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir.child("a.txt").write_str("foo\n").unwrap();
+    tmpdir.child("b.txt").write_str("bar\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root)
+        .template(
+            InPlace::new("")
+                .backup(Backup::Path(root.join("backup.bak")))
+                .clone(),
+        )
+        .run(upcase)
+        .unwrap();
+    assert!(!report.is_success());
+    assert!(report.changed().is_empty());
+    let failed = report.failed();
+    assert_eq!(failed.len(), 2);
+    for (_, e) in failed {
+        assert!(matches!(e, BulkError::InPlace(e) if e.kind() == InPlaceErrorKind::SharedBackup));
+    }
+    assert!(!tmpdir.child("backup.bak").exists());
+    tmpdir.child("a.txt").assert("foo\n");
+    tmpdir.child("b.txt").assert("bar\n");
+}
+
+#[test]
+fn transform_errors() {
+    let tmpdir = TempDir::new().unwrap();
+    tmpdir.child("a.txt").write_str("foo\n").unwrap();
+    tmpdir.child("b.txt").write_str("bad\n").unwrap();
+    let root = tmpdir.path();
+    let report = BulkEdit::new(root)
+        .run(|_, content| {
+            if content.starts_with(b"bad") {
+                Err(String::from("bad content"))
+            } else {
+                Ok(content.to_ascii_uppercase())
+            }
+        })
+        .unwrap();
+    assert!(!report.is_success());
+    assert_eq!(report.changed(), [root.join("a.txt")]);
+    let failed = report.failed();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, root.join("b.txt"));
+    assert!(matches!(&failed[0].1, BulkError::Transform(s) if s == "bad content"));
+    tmpdir.child("a.txt").assert("FOO\n");
+    tmpdir.child("b.txt").assert("bad\n");
+}
+
+#[test]
+fn invalid_glob() {
+    let tmpdir = TempDir::new().unwrap();
+    let r = BulkEdit::new(tmpdir.path()).glob("a{b").run(upcase);
+    assert!(r.is_err());
+}
+
+#[test]
+fn nonexistent_root() {
+    let tmpdir = TempDir::new().unwrap();
+    let root = tmpdir.path().join("nowhere");
+    let report = BulkEdit::new(&root).run(upcase).unwrap();
+    assert!(!report.is_success());
+    let failed = report.failed();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, PathBuf::from(&root));
+    assert!(matches!(failed[0].1, BulkError::Walk(_)));
+}
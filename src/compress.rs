@@ -0,0 +1,91 @@
+use crate::replace_with;
+use std::fs::{File, remove_file};
+use std::io;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// A compression format for backups.  See [`InPlace::compress_backup()`].
+///
+/// [`InPlace::compress_backup()`]: crate::InPlace::compress_backup
+#[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Compress backups with gzip and append `.gz` to their filenames
+    #[cfg(feature = "gzip")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "gzip")))]
+    Gzip,
+
+    /// Compress backups with zstd and append `.zst` to their filenames
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    Zstd,
+}
+
+impl Compression {
+    /// The extension appended to the filenames of backups compressed with
+    /// this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => ".gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Write a compressed copy of `src` to `dest`, replacing any file
+    /// already there, and give it the same permission bits as `src`
+    pub(crate) fn compress(self, src: &Path, dest: &Path) -> io::Result<()> {
+        replace_with(dest, |p| {
+            let mut srcfile = File::open(src)?;
+            let destfile = File::create_new(p)?;
+            let r = self
+                .compress_stream(&mut srcfile, &destfile)
+                .and_then(|()| destfile.set_permissions(srcfile.metadata()?.permissions()));
+            if r.is_err() {
+                drop(destfile);
+                let _ = remove_file(p);
+            }
+            r
+        })
+    }
+
+    fn compress_stream(self, src: &mut File, dest: &File) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut enc = flate2::write::GzEncoder::new(dest, flate2::Compression::default());
+                io::copy(src, &mut enc)?;
+                enc.finish().map(drop)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::copy_encode(src, dest, 0),
+        }
+    }
+
+    /// Replace `path` with the decompressed contents of `backup` via the
+    /// temporary file `tmp` (which must be in the same directory as `path`),
+    /// and then delete `backup`
+    pub(crate) fn decompress_into_place(
+        self,
+        backup: &Path,
+        path: &Path,
+        mut tmp: NamedTempFile,
+    ) -> io::Result<()> {
+        let src = File::open(backup)?;
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                io::copy(&mut flate2::read::GzDecoder::new(&src), tmp.as_file_mut())?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::copy_decode(&src, tmp.as_file_mut())?,
+        }
+        tmp.as_file()
+            .set_permissions(src.metadata()?.permissions())?;
+        drop(src);
+        tmp.persist(path).map_err(|e| e.error)?;
+        remove_file(backup)
+    }
+}
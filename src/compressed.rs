@@ -0,0 +1,354 @@
+use crate::{Compression, InPlace, InPlaceError, InPlaceFile, Output, SavedFile};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// A compressed file that is currently being edited in-place, with its
+/// contents transparently decompressed on reading and compressed on writing.
+///
+/// A `CompressedFile` instance can be obtained via
+/// [`InPlace::open_compressed()`].
+///
+/// `CompressedFile` (and `&CompressedFile`) implements [`Read`], reading the
+/// decompressed contents of the edited file, and [`Write`], compressing the
+/// written bytes into the temporary output file.  In order to update the
+/// edited file with the written data, [`CompressedFile::save()`] must be
+/// called once writing is complete; this finalizes the compressed stream
+/// before the temporary file is persisted.  Alternatively, calling
+/// [`CompressedFile::discard()`] will discard all written data and leave the
+/// edited file unmodified.
+///
+/// Dropping a `CompressedFile` without calling `save()` has the same effect
+/// as calling `discard()`, except that any errors are ignored.
+#[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+pub struct CompressedFile {
+    compression: Compression,
+    decoder: Mutex<Decoder>,
+    encoder: Mutex<Encoder>,
+    output: Output,
+}
+
+impl InPlace {
+    /// Open the edited file for in-place editing as with [`InPlace::open()`],
+    /// treating its contents as compressed with the given format.
+    ///
+    /// If `compression` is `None`, the format is detected from the edited
+    /// file's leading bytes, or, if the file is empty or does not exist, from
+    /// its extension (`.gz` for gzip, `.zst` for zstd).
+    ///
+    /// An empty or nonexistent edited file is read as though it contained no
+    /// compressed data.  The output is always compressed with the detected or
+    /// given format, which can be retrieved with
+    /// [`CompressedFile::compression()`].  The `buffered` setting has no
+    /// effect, as reads & writes are already buffered by the compression
+    /// codec.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::UnknownCompression`] if
+    /// `compression` is `None` and the format cannot be determined.  Returns
+    /// an error of kind [`InPlaceErrorKind::Read`] if reading the edited
+    /// file's leading bytes or setting up decompression fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::UnknownCompression`]: crate::InPlaceErrorKind::UnknownCompression
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+    pub fn open_compressed(
+        &self,
+        compression: Option<Compression>,
+    ) -> Result<CompressedFile, InPlaceError> {
+        let InPlaceFile {
+            mut reader, output, ..
+        } = self.open()?;
+        #[cfg(feature = "merge")]
+        let output = output.without_merge();
+        let mut magic = Vec::with_capacity(4);
+        (&reader)
+            .take(4)
+            .read_to_end(&mut magic)
+            .and_then(|_| reader.seek(SeekFrom::Start(0)))
+            .map_err(InPlaceError::read)?;
+        let compression = compression
+            .or_else(|| {
+                if magic.is_empty() {
+                    Compression::for_path(output.path())
+                } else {
+                    Compression::for_magic(&magic)
+                }
+            })
+            .ok_or_else(InPlaceError::unknown_compression)?;
+        let decoder = if magic.is_empty() {
+            Decoder::Empty
+        } else {
+            Decoder::new(compression, reader).map_err(InPlaceError::read)?
+        };
+        let writer = output.writer().try_clone().map_err(InPlaceError::mktemp)?;
+        let encoder = Encoder::new(compression, writer).map_err(InPlaceError::mktemp)?;
+        Ok(CompressedFile {
+            compression,
+            decoder: Mutex::new(decoder),
+            encoder: Mutex::new(encoder),
+            output,
+        })
+    }
+}
+
+impl CompressedFile {
+    /// The compression format of the edited file
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// The path to the edited file.  If `follow_symlinks` was set to `true`,
+    /// this will be a canonical path; otherwise, the path is only guaranteed
+    /// to be absolute.
+    pub fn path(&self) -> &Path {
+        self.output.path()
+    }
+
+    /// The path to the temporary file that the compressed output is written
+    /// to.  This is an absolute path in the same directory as the edited
+    /// file.
+    pub fn temp_path(&self) -> &Path {
+        self.output.temp_path()
+    }
+
+    /// The path, if any, where the edited file will be backed up once
+    /// [`CompressedFile::save()`] is called.  This is an absolute path.
+    pub fn backup_path(&self) -> Option<&Path> {
+        self.output.backup_path()
+    }
+
+    /// Finalize the compressed output and then save it as with
+    /// [`InPlaceFile::save()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Flush`] if finalizing
+    /// the compressed output fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Flush`]: crate::InPlaceErrorKind::Flush
+    pub fn save(self) -> Result<(), InPlaceError> {
+        self.finish()?.save()
+    }
+
+    /// Finalize the compressed output and then save it as with
+    /// [`InPlaceFile::save_revertible()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Flush`] if finalizing
+    /// the compressed output fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save_revertible()`]: crate::InPlaceFile::save_revertible
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Flush`]: crate::InPlaceErrorKind::Flush
+    pub fn save_revertible(self) -> Result<SavedFile, InPlaceError> {
+        self.finish()?.save_revertible()
+    }
+
+    /// Finish the compressed stream and close the reader, returning the
+    /// output ready for saving
+    fn finish(self) -> Result<Output, InPlaceError> {
+        self.encoder
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .finish()
+            .map_err(InPlaceError::flush)?;
+        drop(self.decoder);
+        Ok(self.output)
+    }
+
+    /// Close all filehandles and do not update or back up the edited file.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn discard(self) -> Result<(), InPlaceError> {
+        drop(self.encoder);
+        drop(self.decoder);
+        self.output.discard()
+    }
+}
+
+impl fmt::Debug for CompressedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedFile")
+            .field("compression", &self.compression)
+            .field("output", &self.output)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for CompressedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Read for &CompressedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decoder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .read(buf)
+    }
+}
+
+impl Write for CompressedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl Write for &CompressedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush()
+    }
+}
+
+impl Compression {
+    /// Determine the compression format of data beginning with `magic`
+    fn for_magic(magic: &[u8]) -> Option<Compression> {
+        match magic {
+            #[cfg(feature = "gzip")]
+            [0x1F, 0x8B, ..] => Some(Compression::Gzip),
+            #[cfg(feature = "zstd")]
+            [0x28, 0xB5, 0x2F, 0xFD] => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Determine the compression format of a file from its extension
+    fn for_path(path: &Path) -> Option<Compression> {
+        let ext = path.extension()?;
+        #[cfg(feature = "gzip")]
+        if ext == "gz" {
+            return Some(Compression::Gzip);
+        }
+        #[cfg(feature = "zstd")]
+        if ext == "zst" {
+            return Some(Compression::Zstd);
+        }
+        let _ = ext;
+        None
+    }
+}
+
+/// The decompressing side of a [`CompressedFile`]
+enum Decoder {
+    /// The edited file is empty or did not exist
+    Empty,
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::read::MultiGzDecoder<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<File>>),
+}
+
+impl Decoder {
+    #[cfg_attr(not(feature = "zstd"), expect(clippy::unnecessary_wraps))]
+    fn new(compression: Compression, reader: File) -> io::Result<Decoder> {
+        match compression {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Ok(Decoder::Gzip(flate2::read::MultiGzDecoder::new(reader))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::read::Decoder::new(reader).map(Decoder::Zstd),
+        }
+    }
+}
+
+impl Read for Decoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Empty => Ok(0),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(dec) => dec.read(buf),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(dec) => dec.read(buf),
+        }
+    }
+}
+
+/// The compressing side of a [`CompressedFile`]
+enum Encoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, File>),
+}
+
+impl Encoder {
+    #[cfg_attr(not(feature = "zstd"), expect(clippy::unnecessary_wraps))]
+    fn new(compression: Compression, writer: File) -> io::Result<Encoder> {
+        match compression {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Ok(Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::write::Encoder::new(writer, 0).map(Encoder::Zstd),
+        }
+    }
+
+    /// Write the end of the compressed stream and flush it to the file
+    fn finish(self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(enc) => enc.finish()?.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(enc) => enc.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(enc) => enc.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(enc) => enc.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(enc) => enc.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(enc) => enc.flush(),
+        }
+    }
+}
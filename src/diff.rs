@@ -0,0 +1,63 @@
+use crate::{InPlaceError, InPlaceFile};
+use similar::TextDiff;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+impl InPlaceFile {
+    /// Return a unified diff between the contents of the edited file and the
+    /// data written to the temporary file so far, with `context` lines of
+    /// context around each hunk.  If there are no differences, the result is
+    /// an empty string.
+    ///
+    /// The original contents are read via the reader file handle, so that
+    /// the diff is against the file that was opened even if another file has
+    /// since been moved to the edited path.  The positions of the reader and
+    /// writer file handles are restored afterwards, and so this method
+    /// should not be called while another thread is reading from or writing
+    /// to the file.  If the edited file did not exist when it was opened, its
+    /// original contents are treated as empty.  Non-UTF-8 contents are
+    /// converted lossily.  Both header lines of the diff name the edited
+    /// path.
+    ///
+    /// This does not save or discard the file.  To preview changes without
+    /// applying them, combine this method with [`InPlace::dry_run()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Flush`] if `buffered` was
+    /// set and flushing buffered writes fails, or of kind
+    /// [`InPlaceErrorKind::Read`] if reading either file fails.
+    ///
+    /// [`InPlace::dry_run()`]: crate::InPlace::dry_run
+    /// [`InPlaceErrorKind::Flush`]: crate::InPlaceErrorKind::Flush
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    #[cfg_attr(docsrs, doc(cfg(feature = "diff")))]
+    pub fn diff(&self, context: usize) -> Result<String, InPlaceError> {
+        let mut writer = self;
+        writer.flush().map_err(InPlaceError::flush)?;
+        let original = read_all(self.reader()).map_err(InPlaceError::read)?;
+        let modified = read_all(self.writer()).map_err(InPlaceError::read)?;
+        let original = String::from_utf8_lossy(&original);
+        let modified = String::from_utf8_lossy(&modified);
+        let name = self.path().display().to_string();
+        let diff = TextDiff::from_lines(&*original, &*modified);
+        Ok(diff
+            .unified_diff()
+            .context_radius(context)
+            .header(&name, &name)
+            .to_string())
+    }
+}
+
+/// Read the entire contents of `file` from the start, and then restore the
+/// file's position
+fn read_all(mut file: &File) -> io::Result<Vec<u8>> {
+    let pos = file.stream_position()?;
+    let mut content = Vec::new();
+    let r = file
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut content));
+    file.seek(SeekFrom::Start(pos))?;
+    r?;
+    Ok(content)
+}
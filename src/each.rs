@@ -0,0 +1,186 @@
+use crate::{Backup, InPlace, InPlaceError, InPlaceFile};
+use std::error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+impl InPlace {
+    /// Edit each path in `paths` in turn, in the manner of `perl -i` or
+    /// `sed -i` operating on a list of files.
+    ///
+    /// Each path is opened using the same settings (backup, symlink handling,
+    /// etc.) as `self`; the path that `self` was constructed with is ignored.
+    /// The resulting [`InPlaceFile`] is passed to `func`.  If `func` returns
+    /// `Ok`, the file is saved; if it returns `Err`, the file is discarded.
+    ///
+    /// If opening a file, `func`, or saving a file fails, the behavior
+    /// depends on `on_error`; see [`OnError`] for the possibilities.  The
+    /// outcome for each path is recorded in the returned [`ForEachReport`].
+    ///
+    /// As every file would be backed up to the same path, a backup specifier
+    /// of [`Backup::Path`] is not supported; if one is set, every path fails
+    /// without being opened with an error of kind
+    /// [`InPlaceErrorKind::SharedBackup`].
+    ///
+    /// [`InPlaceErrorKind::SharedBackup`]: crate::InPlaceErrorKind::SharedBackup
+    pub fn for_each_path<I, F, E>(
+        &self,
+        paths: I,
+        on_error: OnError,
+        mut func: F,
+    ) -> ForEachReport<E>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+        F: FnMut(&mut InPlaceFile) -> Result<(), E>,
+    {
+        let mut report = ForEachReport {
+            results: Vec::new(),
+            aborted: false,
+        };
+        let shared_backup = matches!(self.backup, Some(Backup::Path(_)));
+        let mut inp = self.clone();
+        for p in paths {
+            let path = p.as_ref();
+            inp.path = path.into();
+            let r = if shared_backup {
+                Err(InPlaceError::shared_backup().into())
+            } else {
+                edit(&inp, &mut func)
+            };
+            let outcome = match r {
+                Ok(()) => PathOutcome::Saved,
+                Err(e) => match on_error {
+                    OnError::Abort => {
+                        report.results.push((path.into(), PathOutcome::Failed(e)));
+                        report.aborted = true;
+                        break;
+                    }
+                    OnError::Skip => PathOutcome::Skipped,
+                    OnError::Collect => PathOutcome::Failed(e),
+                },
+            };
+            report.results.push((path.into(), outcome));
+        }
+        report
+    }
+}
+
+fn edit<F, E>(inp: &InPlace, func: &mut F) -> Result<(), ForEachError<E>>
+where
+    F: FnMut(&mut InPlaceFile) -> Result<(), E>,
+{
+    let mut file = inp.open()?;
+    match func(&mut file) {
+        Ok(()) => Ok(file.save()?),
+        Err(e) => {
+            // Report the callback's error rather than any failure to clean
+            // up
+            let _ = file.discard();
+            Err(ForEachError::Callback(e))
+        }
+    }
+}
+
+/// What [`InPlace::for_each_path()`] should do when editing a path fails
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum OnError {
+    /// Stop processing paths.  The failed path is recorded in the report with
+    /// its error, and the remaining paths are not attempted.  This is the
+    /// default.
+    #[default]
+    Abort,
+
+    /// Move on to the next path, recording the failed path in the report as
+    /// [`PathOutcome::Skipped`] without its error
+    Skip,
+
+    /// Move on to the next path, recording the failed path in the report as
+    /// [`PathOutcome::Failed`] along with its error
+    Collect,
+}
+
+/// The results of a call to [`InPlace::for_each_path()`]
+#[derive(Debug)]
+pub struct ForEachReport<E> {
+    results: Vec<(PathBuf, PathOutcome<E>)>,
+    aborted: bool,
+}
+
+impl<E> ForEachReport<E> {
+    /// The outcome for each path that was attempted, in the order that they
+    /// were processed.  The paths are given as they were passed to
+    /// [`InPlace::for_each_path()`].
+    pub fn results(&self) -> &[(PathBuf, PathOutcome<E>)] {
+        &self.results
+    }
+
+    /// Consume the report and return the outcome for each path that was
+    /// attempted
+    pub fn into_results(self) -> Vec<(PathBuf, PathOutcome<E>)> {
+        self.results
+    }
+
+    /// Returns true if processing was stopped early due to an error under
+    /// [`OnError::Abort`]
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Returns true if every path was edited & saved successfully
+    pub fn is_success(&self) -> bool {
+        !self.aborted
+            && self
+                .results
+                .iter()
+                .all(|(_, outcome)| matches!(outcome, PathOutcome::Saved))
+    }
+}
+
+/// The outcome of editing a single path with [`InPlace::for_each_path()`]
+#[derive(Debug)]
+pub enum PathOutcome<E> {
+    /// The file was edited & saved successfully
+    Saved,
+
+    /// Editing the file failed and the error was discarded due to
+    /// [`OnError::Skip`]
+    Skipped,
+
+    /// Editing the file failed with the given error
+    Failed(ForEachError<E>),
+}
+
+/// An error that occurred while editing a single path with
+/// [`InPlace::for_each_path()`]
+#[derive(Debug)]
+pub enum ForEachError<E> {
+    /// Opening or saving the file failed
+    InPlace(InPlaceError),
+
+    /// The callback function returned an error, and the file was discarded
+    Callback(E),
+}
+
+impl<E> From<InPlaceError> for ForEachError<E> {
+    fn from(e: InPlaceError) -> ForEachError<E> {
+        ForEachError::InPlace(e)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ForEachError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForEachError::InPlace(e) => write!(f, "{e}"),
+            ForEachError::Callback(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for ForEachError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ForEachError::InPlace(e) => e.source(),
+            ForEachError::Callback(e) => e.source(),
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{InPlace, InPlaceError};
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter, Serializer};
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::ptr;
+
+impl InPlace {
+    /// Edit the edited file as a JSON document, keeping its formatting.
+    ///
+    /// The edited file is opened with [`InPlace::open()`] and its entire
+    /// contents are parsed into a [`serde_json::Value`], which is then passed
+    /// to `f` for modification.  The return value of `f` is returned by this
+    /// method.
+    ///
+    /// After `f` returns, the value is serialized in the same style as the
+    /// original contents.  If this differs from the serialization of the
+    /// value as originally parsed (including if `f` only changed the order of
+    /// object keys), the file is saved with [`InPlaceFile::save()`].
+    /// Otherwise, the file is discarded with [`InPlaceFile::discard()`], so
+    /// that files left unchanged by `f` are left untouched, and no backup is
+    /// made.  The following aspects of the original style are detected and
+    /// kept:
+    ///
+    /// - the indentation of multi-line documents (any sequence of spaces and
+    ///   tabs), or, for single-line documents, whether commas & colons are
+    ///   followed by a space
+    /// - the order of object keys (new keys are added at the end)
+    /// - whether lines end in LF or CR LF
+    /// - whether the file ends with a line terminator
+    ///
+    /// Whitespace that does not follow a consistent style, such as aligned
+    /// values or arrays written on a single line within a multi-line
+    /// document, is normalized.  Numbers that are left unchanged by `f` (i.e.,
+    /// that compare equal to the number at the same position in the original
+    /// document) are written exactly as they appear in the original contents,
+    /// so that, e.g., `1.50` and `1E3` are not rewritten as `1.5` and
+    /// `1000.0`.  New and changed numbers are written in their shortest form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Read`] if reading the
+    /// edited file fails, of kind [`InPlaceErrorKind::ParseJson`] if the
+    /// contents are not valid JSON (including if the file is empty), or of
+    /// kind [`InPlaceErrorKind::Write`] if writing to the temporary file
+    /// fails.  If parsing fails, `f` is not called.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceFile::discard()`]: crate::InPlaceFile::discard
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::ParseJson`]: crate::InPlaceErrorKind::ParseJson
+    /// [`InPlaceErrorKind::Write`]: crate::InPlaceErrorKind::Write
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn edit_json<F, T>(&self, f: F) -> Result<T, InPlaceError>
+    where
+        F: FnOnce(&mut Value) -> T,
+    {
+        let file = self.open()?;
+        let mut content = Vec::new();
+        file.reader()
+            .read_to_end(&mut content)
+            .map_err(InPlaceError::read)?;
+        let original =
+            serde_json::from_slice::<Value>(&content).map_err(InPlaceError::parse_json)?;
+        let mut value = original.clone();
+        let r = f(&mut value);
+        let format = JsonFormat::detect(&content);
+        let texts = NumberTexts::new(&content, &original);
+        let serialize = |v: &Value| {
+            format
+                .serialize(v, texts.lookup(v, &original))
+                .map_err(|e| InPlaceError::write(e.into()))
+        };
+        let out = serialize(&value)?;
+        if out == serialize(&original)? {
+            file.discard()?;
+        } else {
+            file.writer().write_all(&out).map_err(InPlaceError::write)?;
+            file.save()?;
+        }
+        Ok(r)
+    }
+}
+
+/// The formatting style of a JSON document
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct JsonFormat {
+    /// The indentation of each nesting level, or `None` if the document is
+    /// written on a single line
+    indent: Option<Vec<u8>>,
+    /// For single-line documents, whether commas & colons are followed by a
+    /// space
+    spaced: bool,
+    crlf: bool,
+    final_newline: bool,
+}
+
+impl JsonFormat {
+    /// Detect the formatting style of the JSON document `content`
+    fn detect(content: &[u8]) -> JsonFormat {
+        let body = content.trim_ascii();
+        let indent = body.contains(&b'\n').then(|| {
+            body.split(|&b| b == b'\n')
+                .skip(1)
+                .find_map(|line| {
+                    let n = line
+                        .iter()
+                        .take_while(|&&b| b == b' ' || b == b'\t')
+                        .count();
+                    (n > 0 && !line[n..].trim_ascii().is_empty()).then(|| line[..n].to_vec())
+                })
+                .unwrap_or_default()
+        });
+        let crlf = content
+            .iter()
+            .position(|&b| b == b'\n')
+            .is_some_and(|i| i > 0 && content[i - 1] == b'\r');
+        JsonFormat {
+            indent,
+            spaced: is_spaced(body),
+            crlf,
+            final_newline: content.ends_with(b"\n"),
+        }
+    }
+
+    /// Serialize `value` in this style, writing the numbers in `value` (in
+    /// the order that they are serialized) with the corresponding texts in
+    /// `texts` when given
+    fn serialize(
+        &self,
+        value: &Value,
+        texts: Vec<Option<&[u8]>>,
+    ) -> Result<Vec<u8>, serde_json::Error> {
+        let mut out = Vec::new();
+        let texts = texts.into_iter();
+        match &self.indent {
+            Some(indent) => value.serialize(&mut Serializer::with_formatter(
+                &mut out,
+                NumberFormatter {
+                    inner: PrettyFormatter::with_indent(indent),
+                    texts,
+                },
+            ))?,
+            None if self.spaced => value.serialize(&mut Serializer::with_formatter(
+                &mut out,
+                NumberFormatter {
+                    inner: SpacedFormatter,
+                    texts,
+                },
+            ))?,
+            None => value.serialize(&mut Serializer::with_formatter(
+                &mut out,
+                NumberFormatter {
+                    inner: CompactFormatter,
+                    texts,
+                },
+            ))?,
+        }
+        if self.final_newline {
+            out.push(b'\n');
+        }
+        if self.crlf {
+            // JSON strings cannot contain raw newlines, so every LF in the
+            // output is a line break.
+            let mut crlf_out = Vec::with_capacity(out.len());
+            for b in out {
+                if b == b'\n' {
+                    crlf_out.push(b'\r');
+                }
+                crlf_out.push(b);
+            }
+            out = crlf_out;
+        }
+        Ok(out)
+    }
+}
+
+/// Test whether the first comma or colon outside of a string in the JSON
+/// document `body` is followed by a space
+fn is_spaced(body: &[u8]) -> bool {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &b) in body.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+        } else if b == b'"' {
+            in_string = true;
+        } else if b == b',' || b == b':' {
+            return body.get(i + 1) == Some(&b' ');
+        }
+    }
+    false
+}
+
+/// The original texts of the numbers in a parsed JSON document, keyed by the
+/// addresses of the corresponding [`Number`]s in the parsed [`Value`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct NumberTexts<'a>(HashMap<*const Number, &'a [u8]>);
+
+impl<'a> NumberTexts<'a> {
+    /// Pair the numbers in `document`, the result of parsing `content`, with
+    /// their texts in `content`.  If the numbers cannot be paired up (e.g.,
+    /// because `content` contains duplicate keys), no texts are recorded.
+    fn new(content: &'a [u8], document: &Value) -> NumberTexts<'a> {
+        let mut numbers = Vec::new();
+        collect_numbers(document, &mut numbers);
+        let tokens = number_tokens(content);
+        let mut texts = HashMap::new();
+        if numbers.len() == tokens.len() {
+            for (n, text) in numbers.into_iter().zip(tokens) {
+                if serde_json::from_slice::<Number>(text).is_ok_and(|m| m == *n) {
+                    texts.insert(ptr::from_ref(n), text);
+                }
+            }
+        }
+        NumberTexts(texts)
+    }
+
+    /// For each number in `value`, in the order that they are serialized,
+    /// return the original text of the number at the same position in
+    /// `original`, provided that the two numbers are equal
+    fn lookup(&self, value: &Value, original: &Value) -> Vec<Option<&'a [u8]>> {
+        let mut out = Vec::new();
+        self.lookup_into(value, Some(original), &mut out);
+        out
+    }
+
+    fn lookup_into(
+        &self,
+        value: &Value,
+        original: Option<&Value>,
+        out: &mut Vec<Option<&'a [u8]>>,
+    ) {
+        match value {
+            Value::Number(n) => out.push(match original {
+                Some(Value::Number(m)) if m == n => self.0.get(&ptr::from_ref(m)).copied(),
+                _ => None,
+            }),
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.lookup_into(item, original.and_then(|o| o.get(i)), out);
+                }
+            }
+            Value::Object(map) => {
+                for (key, item) in map {
+                    self.lookup_into(item, original.and_then(|o| o.get(key)), out);
+                }
+            }
+            Value::Null | Value::Bool(_) | Value::String(_) => (),
+        }
+    }
+}
+
+/// Append the numbers in `value` to `out` in the order that they are
+/// serialized
+fn collect_numbers<'v>(value: &'v Value, out: &mut Vec<&'v Number>) {
+    match value {
+        Value::Number(n) => out.push(n),
+        Value::Array(items) => {
+            for item in items {
+                collect_numbers(item, out);
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values() {
+                collect_numbers(item, out);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::String(_) => (),
+    }
+}
+
+/// Return the texts of the numbers outside of strings in the JSON document
+/// `content`, in order
+fn number_tokens(content: &[u8]) -> Vec<&[u8]> {
+    let mut tokens = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = None;
+    for (i, &b) in content.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        let in_number = if start.is_some() {
+            b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E')
+        } else {
+            b.is_ascii_digit() || b == b'-'
+        };
+        match (start, in_number) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                tokens.push(&content[s..i]);
+                start = None;
+            }
+            _ => (),
+        }
+        if b == b'"' {
+            in_string = true;
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&content[s..]);
+    }
+    tokens
+}
+
+/// A formatter that writes numbers with the given original texts, if any,
+/// and otherwise defers to an inner formatter
+#[derive(Debug)]
+struct NumberFormatter<'a, F> {
+    inner: F,
+    texts: std::vec::IntoIter<Option<&'a [u8]>>,
+}
+
+impl<F: Formatter> NumberFormatter<'_, F> {
+    fn write_number<W, G>(&mut self, writer: &mut W, write_default: G) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+        G: FnOnce(&mut F, &mut W) -> io::Result<()>,
+    {
+        match self.texts.next().flatten() {
+            Some(text) => writer.write_all(text),
+            None => write_default(&mut self.inner, writer),
+        }
+    }
+}
+
+impl<F: Formatter> Formatter for NumberFormatter<'_, F> {
+    fn write_i64<W: ?Sized + Write>(&mut self, writer: &mut W, value: i64) -> io::Result<()> {
+        self.write_number(writer, |f, w| f.write_i64(w, value))
+    }
+
+    fn write_u64<W: ?Sized + Write>(&mut self, writer: &mut W, value: u64) -> io::Result<()> {
+        self.write_number(writer, |f, w| f.write_u64(w, value))
+    }
+
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        self.write_number(writer, |f, w| f.write_f64(w, value))
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+/// A formatter for single-line JSON with a space after each comma & colon
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct SpacedFormatter;
+
+impl Formatter for SpacedFormatter {
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,68 @@
+use super::{JsonFormat, NumberTexts, number_tokens};
+use serde_json::Value;
+
+fn roundtrip(content: &str) -> String {
+    let value = serde_json::from_str::<Value>(content).expect("JSON should parse");
+    let texts = NumberTexts::new(content.as_bytes(), &value).lookup(&value, &value);
+    let out = JsonFormat::detect(content.as_bytes())
+        .serialize(&value, texts)
+        .expect("JSON should serialize");
+    String::from_utf8(out).expect("output should be UTF-8")
+}
+
+#[test]
+fn roundtrip_styles() {
+    for content in [
+        "{\n  \"name\": \"in-place\",\n  \"tags\": [\n    \"edit\",\n    \"file\"\n  ]\n}\n",
+        "{\n    \"b\": 1,\n    \"a\": {\n        \"d\": null,\n        \"c\": true\n    }\n}",
+        "{\n\t\"b\": [],\n\t\"a\": {}\n}\n",
+        "{\r\n  \"b\": 2,\r\n  \"a\": 1\r\n}\r\n",
+        "[\n\"x\",\n\"y\"\n]\n",
+        "{\"b\":[1,2],\"a\":\"x, y: z\"}",
+        "{\"b\": [1, 2], \"a\": \"x,y:z\"}\n",
+        "{\"a\": \"\\\", \"}",
+        "42\n",
+        "\"text\"",
+        "[1.0, 1.50, 1E3, -0, 2e-2, 123456789012345678901234567890]\n",
+        "{\"1\": \"2, -3\", \"a\": [4, {\"b\": 5.0}]}",
+    ] {
+        assert_eq!(roundtrip(content), content, "content: {content:?}");
+    }
+}
+
+#[test]
+fn duplicate_keys() {
+    // The first "a" is overwritten, so its number cannot be paired with the
+    // parsed document.
+    let content = "{\"a\": 1.0, \"b\": 2.0, \"a\": 3.0}";
+    assert_eq!(roundtrip(content), "{\"a\": 3.0, \"b\": 2.0}");
+}
+
+#[test]
+fn tokens() {
+    assert_eq!(
+        number_tokens(b"{\"x1\": [-1.5e+3, 0], \"y\": \"\\\"2\"}\n7"),
+        [&b"-1.5e+3"[..], b"0", b"7"]
+    );
+}
+
+#[test]
+fn detect_indent() {
+    for (content, indent) in [
+        ("{}", None),
+        ("{\"a\": 1}\n", None),
+        ("{\n  \"a\": 1\n}", Some(&b"  "[..])),
+        (
+            "\n{\n\n    \"a\": {\n        \"b\": 1\n    }\n}\n",
+            Some(b"    "),
+        ),
+        ("[\n\t1\n]", Some(b"\t")),
+        ("[\n1\n]", Some(b"")),
+    ] {
+        assert_eq!(
+            JsonFormat::detect(content.as_bytes()).indent.as_deref(),
+            indent,
+            "content: {content:?}"
+        );
+    }
+}
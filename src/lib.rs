@@ -65,21 +65,145 @@
 //! `inp.save()` will close the file handles and reset things to the way they
 //! were before.  Any changes are also discarded if `inp` is dropped without
 //! saving, except that in that case any errors are silently ignored.
+//!
+//! Features
+//! ========
+//!
+//! The `in_place` crate has the following optional features:
+//!
+//! - `bulk` — Enables `BulkEdit` for editing every file in a directory tree
+//!   in parallel
+//!
+//! - `diff` — Enables `InPlaceFile::diff()` for producing a unified diff of
+//!   the changes made to a file
+//!
+//! - `encoding` — Enables `InPlace::open_text()` and `TextFile` for editing
+//!   text files in encodings other than UTF-8, such as Latin-1 or UTF-16
+//!
+//! - `gzip` — Enables `Compression::Gzip` for compressing backups with gzip
+//!   via `InPlace::compress_backup()` and for editing gzip-compressed files
+//!   via `InPlace::open_compressed()`
+//!
+//! - `json` — Enables `InPlace::edit_json()` for editing JSON files while
+//!   keeping their formatting
+//!
+//! - `merge` — Enables `InPlace::on_conflict()` and `OnConflict` for
+//!   detecting modifications made to a file while it is being edited and
+//!   merging them with the new contents
+//!
+//! - `reflink` — Enables creating backups with `BackupMethod::Reflink` on
+//!   Linux; without this feature, `BackupMethod::Reflink` always falls back
+//!   to copying
+//!
+//! - `regex` — Enables `InPlace::replace_all()` and
+//!   `InPlace::replace_all_with()` for regex search-and-replace
+//!
+//! - `sed` — Enables `SedScript` and `InPlace::sed()` for editing files with
+//!   scripts in a subset of the `sed` language, along with the `--sed` option
+//!   of the `in-place` command
+//!
+//! - `store` — Enables `Backup::Store` for backing up files to a
+//!   content-addressed store that keeps every version, along with
+//!   `InPlace::list_versions()` and `InPlace::restore_version()`
+//!
+//! - `toml` — Enables `InPlace::edit_toml()` for editing TOML files while
+//!   preserving their comments & formatting
+//!
+//! - `zstd` — Enables `Compression::Zstd` for compressing backups with zstd
+//!   via `InPlace::compress_backup()` and for editing zstd-compressed files
+//!   via `InPlace::open_compressed()`
+
+#![cfg_attr(docsrs, feature(doc_cfg))]
 
 use std::error;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
-use std::fs::{File, metadata, rename, symlink_metadata};
-use std::io;
+use std::fs::{File, Permissions, hard_link, metadata, remove_file, rename, symlink_metadata};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
-use tempfile::{Builder, NamedTempFile, PersistError};
+use std::sync::{Arc, Mutex, PoisonError};
+use tempfile::{Builder, NamedTempFile, PersistError, TempPath};
+
+mod atomic;
+#[cfg(feature = "bulk")]
+mod bulk;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compress;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compressed;
+#[cfg(feature = "diff")]
+mod diff;
+mod each;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "merge")]
+mod merge;
+mod normalize;
+mod patch;
+#[cfg(feature = "regex")]
+mod replace;
+mod restore;
+mod saved;
+#[cfg(feature = "sed")]
+mod sed;
+#[cfg(feature = "store")]
+mod store;
+#[cfg(feature = "encoding")]
+mod text;
+#[cfg(feature = "toml")]
+mod toml;
+pub use crate::atomic::{AtomicWrite, AtomicWriteFile};
+#[cfg(feature = "bulk")]
+pub use crate::bulk::{BulkEdit, BulkError, BulkReport};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use crate::compress::Compression;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use crate::compressed::CompressedFile;
+pub use crate::each::{ForEachError, ForEachReport, OnError, PathOutcome};
+#[cfg(feature = "merge")]
+pub use crate::merge::{Conflict, OnConflict};
+pub use crate::normalize::{LineEnding, NormalizeWriter, TrailingNewlines};
+pub use crate::patch::{AppliedHunk, FailedHunk, Patch, PatchOptions, PatchParseError};
+#[cfg(feature = "regex")]
+pub use crate::replace::ReplaceOptions;
+pub use crate::restore::FileIdentity;
+use crate::saved::Original;
+pub use crate::saved::SavedFile;
+#[cfg(feature = "sed")]
+pub use crate::sed::{SedError, SedScript};
+#[cfg(feature = "store")]
+pub use crate::store::StoredVersion;
+#[cfg(feature = "encoding")]
+pub use crate::text::TextFile;
 
 /// A builder for opening & editing a file in-place.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct InPlace {
     path: PathBuf,
     backup: Option<Backup>,
+    backup_method: BackupMethod,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    compression: Option<Compression>,
     follow_symlinks: bool,
+    temp_name: TempName,
+    defer_permissions: bool,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    create: bool,
+    create_new: bool,
+    #[cfg(unix)]
+    default_mode: Option<u32>,
+    buffered: bool,
+    dry_run: bool,
+    validator: Option<Validator>,
+    #[cfg(feature = "merge")]
+    on_conflict: OnConflict,
+    #[cfg(feature = "encoding")]
+    encoding: Option<&'static encoding_rs::Encoding>,
+    #[cfg(feature = "encoding")]
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+    #[cfg(feature = "encoding")]
+    write_bom: Option<bool>,
 }
 
 impl InPlace {
@@ -89,7 +213,29 @@ impl InPlace {
         InPlace {
             path: path.as_ref().into(),
             backup: None,
+            backup_method: BackupMethod::default(),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compression: None,
             follow_symlinks: true,
+            temp_name: TempName::default(),
+            defer_permissions: false,
+            #[cfg(unix)]
+            mode: None,
+            create: false,
+            create_new: false,
+            #[cfg(unix)]
+            default_mode: None,
+            buffered: false,
+            dry_run: false,
+            validator: None,
+            #[cfg(feature = "merge")]
+            on_conflict: OnConflict::Overwrite,
+            #[cfg(feature = "encoding")]
+            encoding: None,
+            #[cfg(feature = "encoding")]
+            output_encoding: None,
+            #[cfg(feature = "encoding")]
+            write_bom: None,
         }
     }
 
@@ -116,6 +262,30 @@ impl InPlace {
         self
     }
 
+    /// Set how the edited file is backed up when [`InPlaceFile::save()`] is
+    /// called.  The default is [`BackupMethod::Rename`].  See
+    /// [`BackupMethod`] for the options and their fallbacks.
+    ///
+    /// This has no effect unless a backup is set with [`InPlace::backup()`]
+    /// or a hidden backup is made by [`InPlaceFile::save_revertible()`].
+    pub fn backup_method(&mut self, method: BackupMethod) -> &mut Self {
+        self.backup_method = method;
+        self
+    }
+
+    /// Compress the backup of the edited file with the given format.  The
+    /// format's extension (e.g., `.gz`) is appended to the backup path, and
+    /// the compressed backup is fully written before the edited file is
+    /// replaced.  The backup method is ignored when compressing.
+    ///
+    /// This has no effect unless a backup is set with [`InPlace::backup()`].
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+    pub fn compress_backup(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
     /// If `flag` is true (the default), the edited file path will be
     /// canonicalized, resolving any symlinks, before opening.  As a result, if
     /// the edited path points to a symlink, the file that the symlink points
@@ -136,6 +306,214 @@ impl InPlace {
         self
     }
 
+    /// Set the string that the filename of the temporary file starts with.
+    /// The default prefix is `._in_place-`; as it begins with a period, the
+    /// temporary file is hidden on Unix-like systems.  Set the prefix to a
+    /// string that does not start with a period to make the temporary file
+    /// visible.
+    ///
+    /// Any occurrences of `{name}` in the prefix are replaced by the filename
+    /// of the edited path (after canonicalization or absolutization).
+    pub fn temp_prefix<S: Into<OsString>>(&mut self, prefix: S) -> &mut Self {
+        self.temp_name.prefix = prefix.into();
+        self
+    }
+
+    /// Set the string that the filename of the temporary file ends with.  The
+    /// default suffix is empty.
+    ///
+    /// Any occurrences of `{name}` in the suffix are replaced by the filename
+    /// of the edited path (after canonicalization or absolutization).
+    pub fn temp_suffix<S: Into<OsString>>(&mut self, suffix: S) -> &mut Self {
+        self.temp_name.suffix = suffix.into();
+        self
+    }
+
+    /// Set the number of random characters to place between the prefix and
+    /// the suffix of the temporary file's filename.  The default is 6.
+    pub fn temp_rand_len(&mut self, len: usize) -> &mut Self {
+        self.temp_name.rand_len = len;
+        self
+    }
+
+    /// Set both the prefix and the suffix of the temporary file's filename
+    /// from a single template string.  The template is split at the first
+    /// occurrence of `{rand}`, which marks where the random characters go;
+    /// the text before it becomes the prefix, and the text after it becomes
+    /// the suffix.  If the template does not contain `{rand}`, the entire
+    /// template is used as the prefix, and the suffix is set to empty.
+    ///
+    /// As with [`InPlace::temp_prefix()`] and [`InPlace::temp_suffix()`], any
+    /// occurrences of `{name}` are replaced by the filename of the edited
+    /// path.  For example, a template of `.{name}.{rand}.tmp` will cause the
+    /// temporary file for editing `foo.txt` to be named something like
+    /// `.foo.txt.a1B2c3.tmp`.
+    pub fn temp_template(&mut self, template: &str) -> &mut Self {
+        let (prefix, suffix) = template.split_once("{rand}").unwrap_or((template, ""));
+        self.temp_name.prefix = prefix.into();
+        self.temp_name.suffix = suffix.into();
+        self
+    }
+
+    /// If `flag` is true, the edited file's permissions are not copied to the
+    /// temporary file until [`InPlaceFile::save()`] is called, at which point
+    /// its ownership is also copied on Unix on a best-effort basis.  Until
+    /// then, the temporary file keeps the restrictive permissions it was
+    /// created with (mode 0600 on Unix), so that partially-written contents
+    /// are not exposed to other users even if the edited file is
+    /// world-readable, and so that the temporary file remains writable even
+    /// if the edited file is read-only.
+    ///
+    /// If `flag` is false (the default), the permissions are copied to the
+    /// temporary file as soon as it is created, and ownership is not copied.
+    pub fn defer_permissions(&mut self, flag: bool) -> &mut Self {
+        self.defer_permissions = flag;
+        self
+    }
+
+    /// Give the edited file the given permission bits when it is saved
+    /// instead of copying the permissions of the original file.
+    ///
+    /// Like copied permissions, the mode is applied when the temporary file
+    /// is created, or when [`InPlaceFile::save()`] is called if
+    /// [`InPlace::defer_permissions()`] is enabled.  Unlike copied
+    /// permissions, the mode is applied even if the edited path is a symlink
+    /// and `follow_symlinks` is false.
+    #[cfg(unix)]
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// If `flag` is true, [`InPlace::open()`] will succeed even if the edited
+    /// path does not exist.  In that case, the reader file handle will be
+    /// empty, no backup will be made when saving, and [`InPlaceFile::save()`]
+    /// will create the edited file.  If the edited file exists, it is edited
+    /// as normal.  The default is false.
+    ///
+    /// Note that the edited path's parent directory must still exist.  If the
+    /// edited path is a broken symlink and `follow_symlinks` is true,
+    /// [`InPlace::open()`] will fail as usual.
+    pub fn create(&mut self, flag: bool) -> &mut Self {
+        self.create = flag;
+        self
+    }
+
+    /// If `flag` is true, the edited path is required to not exist, both when
+    /// calling [`InPlace::open()`] and when calling [`InPlaceFile::save()`];
+    /// otherwise, this behaves the same as [`InPlace::create()`].  If this is
+    /// set, the value of `create` is ignored.  The default is false.
+    pub fn create_new(&mut self, flag: bool) -> &mut Self {
+        self.create_new = flag;
+        self
+    }
+
+    /// Set the permission bits to give the edited file if it is created due
+    /// to [`InPlace::create()`] or [`InPlace::create_new()`].  If this is not
+    /// set, the file will be given mode 0666 as modified by the process's
+    /// umask.  Any mode set with [`InPlace::mode()`] takes precedence over
+    /// this value.
+    #[cfg(unix)]
+    pub fn default_mode(&mut self, mode: u32) -> &mut Self {
+        self.default_mode = Some(mode);
+        self
+    }
+
+    /// If `flag` is true, reads & writes performed via the [`Read`] and
+    /// [`Write`] implementations of [`InPlaceFile`] are buffered internally.
+    /// Buffered writes are flushed when [`InPlaceFile::save()`] is called.
+    /// The default is false.
+    ///
+    /// Buffering does not apply to the file handles returned by
+    /// [`InPlaceFile::reader()`] and [`InPlaceFile::writer()`].  If buffering
+    /// is enabled, mixing I/O on those handles with I/O via the `Read` and
+    /// `Write` implementations may result in data being read or written out
+    /// of order.
+    pub fn buffered(&mut self, flag: bool) -> &mut Self {
+        self.buffered = flag;
+        self
+    }
+
+    /// If `flag` is true, [`InPlaceFile::save()`] will not modify the edited
+    /// file or create a backup; instead, it will discard the temporary file,
+    /// just like [`InPlaceFile::discard()`].  This is useful for previewing
+    /// changes, e.g., with `InPlaceFile::diff()` (requires the `diff`
+    /// feature).  The default is false.
+    pub fn dry_run(&mut self, flag: bool) -> &mut Self {
+        self.dry_run = flag;
+        self
+    }
+
+    /// Run `func` on the path to the temporary file when
+    /// [`InPlaceFile::save()`] is called, after all data has been written to
+    /// it but before the edited file is backed up or replaced.  If `func`
+    /// returns an error, the temporary file is discarded, the edited file is
+    /// left unmodified, and `save()` fails with an error of kind
+    /// [`InPlaceErrorKind::ValidationFailed`] whose source is the error
+    /// returned by `func`.
+    ///
+    /// `func` could, for example, parse the new contents or run an external
+    /// checker like `nginx -t -c` on them.  Calling this method again
+    /// replaces any previously-set function.
+    pub fn validate<F, E>(&mut self, func: F) -> &mut Self
+    where
+        F: Fn(&Path) -> Result<(), E> + Send + Sync + 'static,
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        self.validator = Some(Validator(Arc::new(move |p| func(p).map_err(Into::into))));
+        self
+    }
+
+    /// Set what [`InPlaceFile::save()`] should do if the edited file was
+    /// modified by someone else after it was opened.  The default is
+    /// [`OnConflict::Overwrite`], which does not check for modifications.
+    ///
+    /// With any other policy, [`InPlace::open()`] reads the entire edited
+    /// file into memory as a snapshot of its original contents, which is
+    /// compared with the edited file's contents on save.  For files opened
+    /// with `InPlace::open_compressed()` (requires the `gzip` or `zstd`
+    /// feature), modifications are never merged, and the merging policies
+    /// behave like [`OnConflict::Fail`].
+    #[cfg(feature = "merge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "merge")))]
+    pub fn on_conflict(&mut self, policy: OnConflict) -> &mut Self {
+        self.on_conflict = policy;
+        self
+    }
+
+    /// Set the encoding of the edited file's contents for
+    /// [`InPlace::open_text()`].  By default, the encoding is detected from a
+    /// byte order mark (BOM) if present, falling back to UTF-8, so this must
+    /// be set in order to edit files in legacy encodings such as windows-1252
+    /// (a superset of Latin-1).
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn encoding(&mut self, encoding: &'static encoding_rs::Encoding) -> &mut Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Set the encoding in which text written via [`InPlace::open_text()`] is
+    /// saved.  By default, this is the encoding of the edited file's
+    /// contents.
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn output_encoding(&mut self, encoding: &'static encoding_rs::Encoding) -> &mut Self {
+        self.output_encoding = Some(encoding);
+        self
+    }
+
+    /// If `flag` is true, text written via [`InPlace::open_text()`] is
+    /// preceded by a byte order mark (BOM), provided that the output encoding
+    /// is UTF-8, UTF-16LE, or UTF-16BE.  By default, a BOM is written if and
+    /// only if the edited file began with one.
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn write_bom(&mut self, flag: bool) -> &mut Self {
+        self.write_bom = Some(flag);
+        self
+    }
+
     /// Open the edited path for reading and create a temporary file for
     /// writing.
     ///
@@ -145,49 +523,262 @@ impl InPlace {
     /// - If `follow_symlinks` is true, the edited path is canonicalized.
     ///   Otherwise, if it is relative, the current directory is prepended.
     ///   (This ensures that changing the current directory while the
-    ///   [`InPlaceFile`] is open will not mess anything up.)
+    ///   [`InPlaceFile`] is open will not mess anything up.)  If `create` or
+    ///   `create_new` is true and the edited path does not exist, its parent
+    ///   directory is canonicalized or absolutized instead.
+    ///
+    /// - If `create_new` is true and the edited path exists, fail.
     ///
     /// - If a backup is set, determine the backup path based on the
     ///   canonicalized/absolutized edited path.  If the result is a relative
     ///   path, the current directory is prepended.
     ///
-    /// - Create a named temporary file in the edited path's parent directory.
+    /// - Create a named temporary file in the edited path's parent directory,
+    ///   named according to the configured temporary file prefix, suffix, and
+    ///   random length.
     ///
-    /// - If the edited path is not a symlink, copy its permission bits to the
-    ///   temporary file.
+    /// - Fetch the edited path's permission bits (and, on Unix, if
+    ///   `defer_permissions` is true, its owner and group) for copying to the
+    ///   temporary file.  Unless `defer_permissions` is true, these are then
+    ///   applied to the temporary file immediately.  If the edited path is a
+    ///   symlink, the permission bits are not copied; an explicitly-set
+    ///   `mode` is always applied.  If the edited path does not exist, `mode`
+    ///   or `default_mode` is used instead.
+    ///
+    /// - Open the edited path for reading.  If the edited path does not exist,
+    ///   an empty anonymous temporary file is opened instead.
     ///
-    /// - Open the edited path for reading.
+    /// - If `on_conflict` is set to anything other than
+    ///   `OnConflict::Overwrite` (requires the `merge` feature), read the
+    ///   entire edited file to take a snapshot (only its length and hash for
+    ///   `OnConflict::Fail`, its full contents otherwise), and then rewind the
+    ///   reader.
+    ///
+    /// - If `buffered` is true, duplicate the reader & writer file handles for
+    ///   use by the internal buffers.
     ///
     /// # Errors
     ///
     /// See the documentation for the variants of [`InPlaceErrorKind`] for the
     /// operations & checks that this method can fail on.
     pub fn open(&self) -> Result<InPlaceFile, InPlaceError> {
-        let path = if self.follow_symlinks {
-            self.path
-                .canonicalize()
-                .map_err(InPlaceError::canonicalize)?
+        #[cfg_attr(not(feature = "merge"), expect(unused_mut))]
+        let mut output = self.open_output()?;
+        let reader = if output.existed {
+            File::open(&output.path)
         } else {
-            absolutize(&self.path)?
+            tempfile::tempfile()
+        }
+        .map_err(InPlaceError::open)?;
+        #[cfg(feature = "merge")]
+        if self.on_conflict != OnConflict::Overwrite {
+            output.snapshot = Some(merge::Snapshot::take(self.on_conflict, &reader)?);
+        }
+        let (read_buf, write_buf) = if self.buffered {
+            let r = reader.try_clone().map_err(InPlaceError::open)?;
+            let w = output.writer().try_clone().map_err(InPlaceError::mktemp)?;
+            (
+                Some(Mutex::new(BufReader::new(r))),
+                Some(Mutex::new(BufWriter::new(w))),
+            )
+        } else {
+            (None, None)
         };
+        Ok(InPlaceFile {
+            reader,
+            read_buf,
+            write_buf,
+            output,
+        })
+    }
+
+    /// Perform all the steps of [`InPlace::open()`] other than opening the
+    /// edited path for reading
+    pub(crate) fn open_output(&self) -> Result<Output, InPlaceError> {
+        let (path, exists) = self.resolve()?;
+        if exists && self.create_new {
+            return Err(InPlaceError::already_exists());
+        }
         // Don't try to canonicalize backup_path, as it likely won't exist,
         // which would lead to an error
-        let backup_path = match self.backup.as_ref() {
-            Some(bkp) => Some(absolutize(&bkp.apply(&path)?)?),
-            None => None,
+        let backup_path = match self
+            .backup
+            .as_ref()
+            .map(|bkp| bkp.apply(&path))
+            .transpose()?
+        {
+            Some(Some(bp)) => Some(self.compressed_path(absolutize(&bp)?)),
+            _ => None,
         };
-        let writer = mktemp(&path)?;
-        copystats(&path, writer.as_file(), self.follow_symlinks)?;
-        let reader = File::open(&path).map_err(InPlaceError::open)?;
-        Ok(InPlaceFile {
-            reader,
+        #[cfg(feature = "store")]
+        let store = match self.backup.as_ref() {
+            Some(Backup::Store(dir)) => Some(absolutize(dir)?),
+            _ => None,
+        };
+        let (writer, stats) = if exists {
+            let writer = self.temp_name.mktemp(&path, None)?;
+            (writer, self.getstats(&path)?)
+        } else {
+            self.mktemp_new(&path)?
+        };
+        let stats = if self.defer_permissions {
+            Some(stats)
+        } else {
+            stats.apply(writer.as_file())?;
+            None
+        };
+        Ok(Output {
             writer,
             path,
             backup_path,
+            stats,
+            existed: exists,
+            noclobber: self.create_new,
+            backup_method: self.backup_method,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            compression: self.compression,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            temp_name: self.temp_name.clone(),
+            #[cfg(feature = "store")]
+            store,
+            #[cfg(feature = "merge")]
+            snapshot: None,
+            dry_run: self.dry_run,
+            validator: self.validator.clone(),
+        })
+    }
+
+    /// Append the extension for the backup compression format, if any, to
+    /// `backup_path`
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "zstd")),
+        expect(clippy::unused_self)
+    )]
+    fn compressed_path(&self, backup_path: PathBuf) -> PathBuf {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(c) = self.compression {
+            let mut p = backup_path.into_os_string();
+            p.push(c.extension());
+            return p.into();
+        }
+        backup_path
+    }
+
+    /// Canonicalize or absolutize the edited path, and determine whether it
+    /// exists.  If neither `create` nor `create_new` is set, the path is
+    /// assumed to exist.
+    fn resolve(&self) -> Result<(PathBuf, bool), InPlaceError> {
+        let creating = self.create || self.create_new;
+        if self.follow_symlinks {
+            match self.path.canonicalize() {
+                Ok(path) => Ok((path, true)),
+                Err(e) if creating && is_missing(&self.path) => {
+                    let fname = self
+                        .path
+                        .file_name()
+                        .ok_or_else(InPlaceError::no_filename)?;
+                    let parent = match self.path.parent() {
+                        Some(p) if p != Path::new("") => p,
+                        _ => Path::new("."),
+                    };
+                    let parent = parent
+                        .canonicalize()
+                        .map_err(|_| InPlaceError::canonicalize(e))?;
+                    Ok((parent.join(fname), false))
+                }
+                Err(e) => Err(InPlaceError::canonicalize(e)),
+            }
+        } else {
+            let path = absolutize(&self.path)?;
+            let exists = !(creating && is_missing(&path));
+            Ok((path, exists))
+        }
+    }
+
+    /// Create the temporary file for an edited path that does not exist yet,
+    /// and determine the permissions to give it
+    #[cfg(unix)]
+    fn mktemp_new(&self, path: &Path) -> Result<(NamedTempFile, Stats), InPlaceError> {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = self.mode.or(self.default_mode) {
+            let writer = self.temp_name.mktemp(path, None)?;
+            let stats = Stats {
+                permissions: Some(Permissions::from_mode(mode)),
+                owner: None,
+            };
+            return Ok((writer, stats));
+        }
+        // Create the temporary file with mode 0666 and let the umask decide
+        // what the permissions will actually be
+        let writer = self
+            .temp_name
+            .mktemp(path, Some(Permissions::from_mode(0o666)))?;
+        let permissions = writer
+            .as_file()
+            .metadata()
+            .map_err(InPlaceError::get_metadata)?
+            .permissions();
+        if self.defer_permissions {
+            writer
+                .as_file()
+                .set_permissions(Permissions::from_mode(0o600))
+                .map_err(InPlaceError::set_metadata)?;
+        }
+        let stats = Stats {
+            permissions: Some(permissions),
+            owner: None,
+        };
+        Ok((writer, stats))
+    }
+
+    #[cfg(not(unix))]
+    fn mktemp_new(&self, path: &Path) -> Result<(NamedTempFile, Stats), InPlaceError> {
+        let writer = self.temp_name.mktemp(path, None)?;
+        Ok((writer, Stats { permissions: None }))
+    }
+
+    fn getstats(&self, path: &Path) -> Result<Stats, InPlaceError> {
+        let md = if self.follow_symlinks {
+            metadata(path)
+        } else {
+            symlink_metadata(path)
+        }
+        .map_err(InPlaceError::get_metadata)?;
+        #[cfg_attr(not(unix), expect(unused_mut))]
+        let mut permissions = (!md.is_symlink()).then(|| md.permissions());
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            permissions = Some(std::os::unix::fs::PermissionsExt::from_mode(mode));
+        }
+        Ok(Stats {
+            permissions,
+            #[cfg(unix)]
+            // Ownership is only copied in the deferred mode, so that the
+            // default behavior is unchanged.
+            owner: (self.defer_permissions && !md.is_symlink()).then(|| {
+                use std::os::unix::fs::MetadataExt;
+                (md.uid(), md.gid())
+            }),
         })
     }
 }
 
+/// Test whether `a` and `b` refer to the same file, either by being equal
+/// paths or (on Unix) by being existing paths to the same inode
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(ma), Ok(mb)) = (metadata(a), metadata(b)) {
+            return ma.dev() == mb.dev() && ma.ino() == mb.ino();
+        }
+    }
+    false
+}
+
 /// A path or path computation specifying where to back up an edited file.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Backup {
@@ -203,26 +794,39 @@ pub enum Backup {
     /// Determine the path at which to backup the edited file by appending the
     /// given value to the filename
     Append(OsString),
+    /// Back up the edited file to a content-addressed store in the given
+    /// directory, which keeps every backed-up version of every file edited
+    /// with it while storing identical contents only once.  Stored versions
+    /// can be queried with [`InPlace::list_versions()`] and restored with
+    /// [`InPlace::restore_version()`].
+    ///
+    /// The backup method and backup compression settings have no effect on
+    /// backups made to a store.
+    #[cfg(feature = "store")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "store")))]
+    Store(PathBuf),
 }
 
 impl Backup {
-    fn apply(&self, path: &Path) -> Result<PathBuf, InPlaceError> {
+    /// Compute the path at which to back up `path`.  Returns `None` for
+    /// backups that are not made to a single path.
+    fn apply(&self, path: &Path) -> Result<Option<PathBuf>, InPlaceError> {
         match self {
             Backup::Path(p) => {
                 if p == Path::new("") {
                     Err(InPlaceError::empty_backup())
                 } else {
-                    Ok(p.clone())
+                    Ok(Some(p.clone()))
                 }
             }
             Backup::FileName(fname) => {
                 if fname.is_empty() {
                     Err(InPlaceError::empty_backup())
                 } else {
-                    Ok(path.with_file_name(fname))
+                    Ok(Some(path.with_file_name(fname)))
                 }
             }
-            Backup::Extension(ext) => Ok(path.with_extension(ext)),
+            Backup::Extension(ext) => Ok(Some(path.with_extension(ext))),
             Backup::Append(ext) => {
                 if ext.is_empty() {
                     Err(InPlaceError::empty_backup())
@@ -231,16 +835,125 @@ impl Backup {
                         Some(fname) => {
                             let mut fname = fname.to_os_string();
                             fname.push(ext);
-                            Ok(path.with_file_name(&fname))
+                            Ok(Some(path.with_file_name(&fname)))
                         }
                         None => Err(InPlaceError::no_filename()),
                     }
                 }
             }
+            #[cfg(feature = "store")]
+            Backup::Store(dir) => {
+                if dir == Path::new("") {
+                    Err(InPlaceError::empty_backup())
+                } else {
+                    Ok(None)
+                }
+            }
         }
     }
 }
 
+/// How to back up an edited file when it is saved
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum BackupMethod {
+    /// Move the edited file to the backup path.  The edited path briefly does
+    /// not exist between the move and the temporary file being moved into
+    /// its place.  This is the default.
+    #[default]
+    Rename,
+    /// Create a hard link to the edited file at the backup path, so that the
+    /// edited path always exists.  If this fails (e.g., because the backup
+    /// path is on a different filesystem), [`BackupMethod::Copy`] is used
+    /// instead.
+    HardLink,
+    /// Create a reflink (a copy-on-write clone that shares storage with the
+    /// original) of the edited file at the backup path using `FICLONE`.  This
+    /// is only supported on Linux on filesystems such as Btrfs and XFS, and
+    /// only if the `reflink` feature is enabled; otherwise, or if it fails,
+    /// [`BackupMethod::Copy`] is used instead.
+    Reflink,
+    /// Copy the contents & permission bits of the edited file to the backup
+    /// path
+    Copy,
+}
+
+impl BackupMethod {
+    /// Back up `path` to `backup_path`, falling back to copying if necessary.
+    /// Returns true if `path` was moved rather than linked or copied.
+    ///
+    /// Other than with `Rename`, the backup is created at a temporary path
+    /// and then moved to `backup_path`, replacing any file already there.  If
+    /// `path` is a symlink, it is always moved, so that the backup is a
+    /// symlink as well.
+    fn apply(self, path: &Path, backup_path: &Path) -> io::Result<bool> {
+        if self == BackupMethod::Rename
+            || symlink_metadata(path).is_ok_and(|md| md.file_type().is_symlink())
+        {
+            return rename(path, backup_path).map(|()| true);
+        }
+        let r = match self {
+            BackupMethod::HardLink => replace_with(backup_path, |p| hard_link(path, p)),
+            BackupMethod::Reflink => replace_with(backup_path, |p| reflink(path, p)),
+            _ => Err(io::ErrorKind::Unsupported.into()),
+        };
+        r.or_else(|_| replace_with(backup_path, |p| copy_file(path, p)))
+            .map(|()| false)
+    }
+}
+
+/// Create a file at a temporary path in the same directory as `dest` by
+/// calling `create` on the path, and then move it to `dest`
+fn replace_with<F>(dest: &Path, create: F) -> io::Result<()>
+where
+    F: FnMut(&Path) -> io::Result<()>,
+{
+    let dir = match dest.parent() {
+        Some(p) if p != Path::new("") => p,
+        _ => Path::new("."),
+    };
+    Builder::new()
+        .prefix("._in_place-backup-")
+        .make_in(dir, create)?
+        .into_temp_path()
+        .persist(dest)
+        .map_err(|e| e.error)
+}
+
+/// Copy the contents & permission bits of `src` to a new file at `dest`
+fn copy_file(src: &Path, dest: &Path) -> io::Result<()> {
+    let mut srcfile = File::open(src)?;
+    let mut destfile = File::create_new(dest)?;
+    let r = io::copy(&mut srcfile, &mut destfile)
+        .and_then(|_| destfile.set_permissions(srcfile.metadata()?.permissions()));
+    if r.is_err() {
+        drop(destfile);
+        let _ = remove_file(dest);
+    }
+    r
+}
+
+/// Create a reflink of `src` at a new file at `dest`, and copy over the
+/// permission bits
+#[cfg(all(target_os = "linux", feature = "reflink"))]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    let srcfile = File::open(src)?;
+    let destfile = File::create_new(dest)?;
+    let r = rustix::fs::ioctl_ficlone(&destfile, &srcfile)
+        .map_err(io::Error::from)
+        .and_then(|()| destfile.set_permissions(srcfile.metadata()?.permissions()));
+    if r.is_err() {
+        drop(destfile);
+        let _ = remove_file(dest);
+    }
+    r
+}
+
+#[cfg(not(all(target_os = "linux", feature = "reflink")))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
 /// A file that is currently being edited in-place.
 ///
 /// An `InPlaceFile` instance can be obtained via [`InPlace::open()`].
@@ -254,12 +967,16 @@ impl Backup {
 ///
 /// Dropping an `InPlaceFile` without calling `save()` has the same effect as
 /// calling `discard()`, except that any errors are ignored.
+///
+/// `InPlaceFile` (and `&InPlaceFile`) also implements [`Read`], reading from
+/// the reader file handle, and [`Write`], writing to the writer file handle.
+/// If [`InPlace::buffered()`] was enabled, these reads & writes are buffered.
 #[derive(Debug)]
 pub struct InPlaceFile {
     reader: File,
-    writer: NamedTempFile,
-    path: PathBuf,
-    backup_path: Option<PathBuf>,
+    read_buf: Option<Mutex<BufReader<File>>>,
+    write_buf: Option<Mutex<BufWriter<File>>>,
+    output: Output,
 }
 
 impl InPlaceFile {
@@ -270,20 +987,50 @@ impl InPlaceFile {
 
     /// The writer file handle
     pub fn writer(&self) -> &File {
-        self.writer.as_file()
+        self.output.writer()
     }
 
     /// The path to the edited file.  If `follow_symlinks` was set to `true`,
     /// this will be a canonical path; otherwise, the path is only guaranteed
     /// to be absolute.
     pub fn path(&self) -> &Path {
-        &self.path
+        self.output.path()
+    }
+
+    /// The path to the temporary file that the writer file handle writes to.
+    /// This is an absolute path in the same directory as the edited file.
+    pub fn temp_path(&self) -> &Path {
+        self.output.temp_path()
     }
 
     /// The path, if any, where the edited file will be backed up once
     /// [`InPlaceFile::save()`] is called.  This is an absolute path.
     pub fn backup_path(&self) -> Option<&Path> {
-        self.backup_path.as_deref()
+        self.output.backup_path()
+    }
+
+    /// Return an iterator over the remaining lines of the edited file, as
+    /// with [`BufRead::lines()`].
+    ///
+    /// The returned iterator reads via the [`Read`] implementation for
+    /// `&InPlaceFile` through a [`BufReader`], and so, if the iterator is
+    /// dropped before it is exhausted, any data that it read ahead is lost.
+    pub fn lines(&self) -> io::Lines<BufReader<&InPlaceFile>> {
+        BufReader::new(self).lines()
+    }
+
+    /// Read the remaining contents of the edited file into a new `String`.
+    /// To append to an existing `String` instead, use
+    /// [`Read::read_to_string()`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading fails or if the contents are not valid UTF-8.
+    pub fn read_remaining(&self) -> io::Result<String> {
+        let mut s = String::new();
+        let mut reader = self;
+        reader.read_to_string(&mut s)?;
+        Ok(s)
     }
 
     /// Save the unmodified edited file at the backup path, if any, and replace
@@ -292,32 +1039,77 @@ impl InPlaceFile {
     /// The exact set & order of operations may change in a future version, but
     /// currently it is as follows:
     ///
+    /// - If `buffered` was set, any buffered writes are flushed to the
+    ///   temporary file.
+    ///
     /// - The file handle for the edited file is closed.
     ///
-    /// - If a backup path is set, move the edited file to that location.
+    /// - If `defer_permissions` was set, the edited file's permissions (and,
+    ///   on Unix, its ownership) are copied to the temporary file.
+    ///
+    /// - If `on_conflict` was set to anything other than
+    ///   `OnConflict::Overwrite` (requires the `merge` feature), the contents
+    ///   of the edited path are compared with the snapshot taken when the
+    ///   file was opened.  If they differ, then, depending on the policy,
+    ///   either the temporary file is deleted and no further steps are
+    ///   performed, or the changes are merged into the temporary file.
+    ///
+    /// - If a validation function was set, it is called on the temporary
+    ///   file's path.  If it fails, the temporary file is deleted, and no
+    ///   further steps are performed.
+    ///
+    /// - If `dry_run` was set, the temporary file is deleted as with
+    ///   [`InPlaceFile::discard()`], and no further steps are performed.
+    ///
+    /// - If a backup path is set, move the edited file to that location (or
+    ///   link or copy it there, depending on the `backup_method`).  If the
+    ///   edited file did not exist when it was opened and still does not
+    ///   exist, this step is skipped.  If `create_new` was set, this step is
+    ///   always skipped.
     ///
     /// - Persist the temporary file at the edited file's original location.
-    ///   If this fails, and a backup path is set, try to move the backup back
-    ///   to the original location, ignoring any errors.
+    ///   If `create_new` was set, this fails if the edited path exists.  If
+    ///   this fails, and the edited file was moved to a backup path, try to
+    ///   move the backup back to the original location, ignoring any errors;
+    ///   if the edited file was instead linked or copied, try to delete the
+    ///   backup.
     ///
     /// # Errors
     ///
     /// See the documentation for the variants of [`InPlaceErrorKind`] for the
     /// operations that this method can fail on.
     pub fn save(self) -> Result<(), InPlaceError> {
-        drop(self.reader);
-        if let Some(bp) = self.backup_path.as_ref() {
-            rename(&self.path, bp).map_err(InPlaceError::save_backup)?;
-        }
-        match self.writer.persist(&self.path) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                if let Some(bp) = self.backup_path.as_ref() {
-                    let _ = rename(bp, &self.path);
-                }
-                Err(InPlaceError::persist(e))
-            }
+        self.finish()?.save()
+    }
+
+    /// Save the edited file as with [`InPlaceFile::save()`], and return a
+    /// [`SavedFile`] that can be used to undo the edit afterwards.
+    ///
+    /// If no backup path is set, or if the backup path refers to the edited
+    /// file itself, the edited file is moved to a hidden backup file (named
+    /// with the prefix `._in_place-backup-`) in the same directory instead of
+    /// being overwritten.  This hidden backup is deleted when the `SavedFile`
+    /// is finalized or dropped.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    pub fn save_revertible(self) -> Result<SavedFile, InPlaceError> {
+        self.finish()?.save_revertible()
+    }
+
+    /// Flush any buffered writes and close the reader, returning the output
+    /// ready for saving
+    fn finish(self) -> Result<Output, InPlaceError> {
+        if let Some(wbuf) = self.write_buf {
+            let wbuf = wbuf.into_inner().unwrap_or_else(PoisonError::into_inner);
+            wbuf.into_inner()
+                .map_err(|e| InPlaceError::flush(e.into_error()))?;
         }
+        drop(self.read_buf);
+        drop(self.reader);
+        Ok(self.output)
     }
 
     /// Close all filehandles and do not update or back up the edited file.
@@ -327,24 +1119,268 @@ impl InPlaceFile {
     /// See the documentation for the variants of [`InPlaceErrorKind`] for the
     /// operations that this method can fail on.
     pub fn discard(self) -> Result<(), InPlaceError> {
+        if let Some(wbuf) = self.write_buf {
+            // Throw away any buffered data without flushing it
+            let _ = wbuf
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+                .into_parts();
+        }
+        self.output.discard()
+    }
+}
+
+impl Read for InPlaceFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Read for &InPlaceFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.read_buf.as_ref() {
+            Some(rbuf) => rbuf
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .read(buf),
+            None => (&self.reader).read(buf),
+        }
+    }
+}
+
+impl Write for InPlaceFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl Write for &InPlaceFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.write_buf.as_ref() {
+            Some(wbuf) => wbuf
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write(buf),
+            None => self.output.writer().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.write_buf.as_ref() {
+            Some(wbuf) => wbuf.lock().unwrap_or_else(PoisonError::into_inner).flush(),
+            None => self.output.writer().flush(),
+        }
+    }
+}
+
+/// The temporary file that new contents for an edited path are written to,
+/// along with the details needed to put it in place.  This is the part of
+/// [`InPlaceFile`] that is shared with [`AtomicWriteFile`].
+#[derive(Debug)]
+pub(crate) struct Output {
+    writer: NamedTempFile,
+    path: PathBuf,
+    backup_path: Option<PathBuf>,
+    stats: Option<Stats>,
+    existed: bool,
+    noclobber: bool,
+    backup_method: BackupMethod,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    compression: Option<Compression>,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    temp_name: TempName,
+    #[cfg(feature = "store")]
+    store: Option<PathBuf>,
+    #[cfg(feature = "merge")]
+    snapshot: Option<merge::Snapshot>,
+    dry_run: bool,
+    validator: Option<Validator>,
+}
+
+impl Output {
+    pub(crate) fn writer(&self) -> &File {
+        self.writer.as_file()
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn temp_path(&self) -> &Path {
+        self.writer.path()
+    }
+
+    pub(crate) fn backup_path(&self) -> Option<&Path> {
+        self.backup_path.as_deref()
+    }
+
+    pub(crate) fn save(self) -> Result<(), InPlaceError> {
+        self.persist(false).map(drop)
+    }
+
+    pub(crate) fn save_revertible(self) -> Result<SavedFile, InPlaceError> {
+        let path = self.path.clone();
+        let original = self.persist(true)?;
+        Ok(SavedFile::new(path, original))
+    }
+
+    /// Put the temporary file in place, returning where the original file
+    /// can be found.  If `revertible` is true and there is no backup path,
+    /// the original file is moved to a hidden backup.
+    fn persist(self, revertible: bool) -> Result<Original, InPlaceError> {
+        if let Some(stats) = self.stats.as_ref() {
+            stats.apply(self.writer.as_file())?;
+        }
+        #[cfg(feature = "merge")]
+        if let Err(e) = self.check_conflict() {
+            let _ = self.writer.close();
+            return Err(e);
+        }
+        if let Some(validator) = self.validator.as_ref() {
+            if let Err(e) = (validator.0)(self.writer.path()) {
+                // Report the validation error rather than any failure to
+                // clean up
+                let _ = self.writer.close();
+                return Err(InPlaceError::validation_failed(e));
+            }
+        }
+        if self.dry_run {
+            self.discard()?;
+            return Ok(Original::Unmodified);
+        }
+        let mut original = Original::Missing;
+        let mut moved = false;
+        let mut created = false;
+        if !self.noclobber {
+            #[cfg(feature = "store")]
+            if let Some(dir) = self.store.as_deref() {
+                match store::BackupStore(dir).add(&self.path) {
+                    Ok(blob) => original = Original::Stored(blob),
+                    Err(e) if !self.existed && e.kind() == io::ErrorKind::NotFound => (),
+                    Err(e) => return Err(InPlaceError::save_backup(e)),
+                }
+            }
+            let backup = match self.backup_path.as_ref() {
+                // A backup at the edited path itself would be overwritten by
+                // the new contents, leaving nothing to revert to
+                Some(bp) if revertible && is_same_file(bp, &self.path) => {
+                    Some(Original::Hidden(self.hidden_backup()?))
+                }
+                Some(bp) => Some(self.backup_original(bp)),
+                None if revertible && original.location().is_none() => {
+                    Some(Original::Hidden(self.hidden_backup()?))
+                }
+                None => None,
+            };
+            if let Some(backup) = backup {
+                if let Some(location) = backup.location() {
+                    let r = match backup {
+                        #[cfg(any(feature = "gzip", feature = "zstd"))]
+                        Original::Compressed(_, c, _) => {
+                            c.compress(&self.path, location).map(|()| false)
+                        }
+                        _ => self.backup_method.apply(&self.path, location),
+                    };
+                    match r {
+                        Ok(mv) => {
+                            moved = mv;
+                            created = true;
+                            original = backup;
+                        }
+                        Err(e) if !self.existed && e.kind() == io::ErrorKind::NotFound => (),
+                        Err(e) => return Err(InPlaceError::save_backup(e)),
+                    }
+                }
+            }
+        }
+        let r = if self.noclobber {
+            self.writer.persist_noclobber(&self.path)
+        } else {
+            self.writer.persist(&self.path)
+        };
+        match r {
+            Ok(_) => Ok(original),
+            Err(e) => {
+                // Stored contents may be shared with other backups, so they
+                // are left in the store.
+                if let Some(location) = original.location().filter(|_| created) {
+                    if moved {
+                        let _ = rename(location, &self.path);
+                    } else {
+                        let _ = remove_file(location);
+                    }
+                }
+                Err(InPlaceError::persist(e))
+            }
+        }
+    }
+
+    /// Describe where the original file will be stored when backed up to
+    /// `backup_path`
+    fn backup_original(&self, backup_path: &Path) -> Original {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(c) = self.compression {
+            return Original::Compressed(backup_path.into(), c, self.temp_name.clone());
+        }
+        Original::Backup(backup_path.into())
+    }
+
+    /// Reserve a path in the edited file's directory for a hidden backup
+    fn hidden_backup(&self) -> Result<TempPath, InPlaceError> {
+        let parent = self.path.parent().ok_or_else(InPlaceError::no_parent)?;
+        Builder::new()
+            .prefix("._in_place-backup-")
+            .tempfile_in(parent)
+            .map(NamedTempFile::into_temp_path)
+            .map_err(InPlaceError::save_backup)
+    }
+
+    pub(crate) fn discard(self) -> Result<(), InPlaceError> {
         self.writer.close().map_err(InPlaceError::rmtemp)
     }
 }
 
 /// An error that can occur while opening, saving, or discarding an
-/// [`InPlaceFile`].
+/// [`InPlaceFile`] or [`AtomicWriteFile`].
 ///
-/// Some errors are caused by failed I/O operations, while others are responses
-/// to invalid paths or backup specifiers.  Only the first kind have source
-/// errors, available via [`InPlaceError::as_io_error()`] and
-/// [`InPlaceError::into_io_error()`] in addition to
-/// [`std::error::Error::source()`].
+/// Some errors are caused by failed I/O operations or by a validation
+/// function rejecting the new contents, while others are responses to invalid
+/// paths or backup specifiers.  Only the first kind have source errors,
+/// available via [`InPlaceError::as_io_error()`] and
+/// [`InPlaceError::into_io_error()`] (for I/O errors) or
+/// [`InPlaceError::into_validation_error()`] (for validation errors) in
+/// addition to [`std::error::Error::source()`].  Errors for patches that do
+/// not apply also list the failed hunks via
+/// [`InPlaceError::failed_hunks()`], and errors for conflicting merges list
+/// the conflicts via `InPlaceError::merge_conflicts()` (requires the `merge`
+/// feature).
 #[derive(Debug)]
 pub struct InPlaceError {
     kind: InPlaceErrorKind,
-    source: Option<io::Error>,
+    source: Option<ErrorSource>,
 }
 
+/// The source error of an [`InPlaceError`]
+#[derive(Debug)]
+enum ErrorSource {
+    Io(io::Error),
+    Validation(BoxError),
+    Patch(patch::HunksFailed),
+    #[cfg(feature = "merge")]
+    Merge(merge::MergeConflicts),
+    #[cfg(feature = "toml")]
+    Toml(toml_edit::TomlError),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+}
+
+/// A boxed error returned by a validation function
+type BoxError = Box<dyn error::Error + Send + Sync>;
+
 impl InPlaceError {
     /// Returns an enum value describing the operation or check that failed
     pub fn kind(&self) -> InPlaceErrorKind {
@@ -355,26 +1391,62 @@ impl InPlaceError {
     /// documentation of [`InPlaceErrorKind`] to find out which error kinds
     /// have source errors.
     pub fn as_io_error(&self) -> Option<&io::Error> {
-        self.source.as_ref()
+        match self.source.as_ref() {
+            Some(ErrorSource::Io(e)) => Some(e),
+            _ => None,
+        }
     }
 
     /// Consumes the [`InPlaceError`] and returns the inner [`std::io::Error`],
     /// if any.
     pub fn into_io_error(self) -> Option<io::Error> {
-        self.source
+        match self.source {
+            Some(ErrorSource::Io(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Consumes the [`InPlaceError`] and returns the error returned by the
+    /// validation function set with [`InPlace::validate()`], if this is an
+    /// error of kind [`InPlaceErrorKind::ValidationFailed`]
+    pub fn into_validation_error(self) -> Option<Box<dyn error::Error + Send + Sync>> {
+        match self.source {
+            Some(ErrorSource::Validation(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Returns the hunks of the patch that could not be applied, if this is
+    /// an error of kind [`InPlaceErrorKind::PatchFailed`]
+    pub fn failed_hunks(&self) -> Option<&[FailedHunk]> {
+        match self.source.as_ref() {
+            Some(ErrorSource::Patch(e)) => Some(&e.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the conflicting regions of the merge, if this is an error of
+    /// kind [`InPlaceErrorKind::MergeConflict`]
+    #[cfg(feature = "merge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "merge")))]
+    pub fn merge_conflicts(&self) -> Option<&[Conflict]> {
+        match self.source.as_ref() {
+            Some(ErrorSource::Merge(e)) => Some(&e.0),
+            _ => None,
+        }
     }
 
     fn get_metadata(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::GetMetadata,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn set_metadata(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::SetMetadata,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
@@ -388,28 +1460,35 @@ impl InPlaceError {
     fn mktemp(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::Mktemp,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn canonicalize(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::Canonicalize,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn cwd(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::CurrentDir,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn open(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::Open,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    fn already_exists() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::AlreadyExists,
+            source: None,
         }
     }
 
@@ -420,6 +1499,13 @@ impl InPlaceError {
         }
     }
 
+    fn shared_backup() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::SharedBackup,
+            source: None,
+        }
+    }
+
     fn no_filename() -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::NoFilename,
@@ -427,24 +1513,146 @@ impl InPlaceError {
         }
     }
 
+    fn read(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Read,
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
     fn save_backup(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::SaveBackup,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn persist(source: PersistError) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::PersistTemp,
-            source: Some(source.error),
+            source: Some(ErrorSource::Io(source.error)),
+        }
+    }
+
+    fn no_backup() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::NoBackup,
+            source: None,
+        }
+    }
+
+    fn modified() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Modified,
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "store")]
+    fn read_store(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::ReadStore,
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    fn restore(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Restore,
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    fn revert(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Revert,
+            source: Some(ErrorSource::Io(source)),
         }
     }
 
     fn rmtemp(source: io::Error) -> InPlaceError {
         InPlaceError {
             kind: InPlaceErrorKind::Rmtemp,
-            source: Some(source),
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    fn write(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Write,
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    fn flush(source: io::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Flush,
+            source: Some(ErrorSource::Io(source)),
+        }
+    }
+
+    #[cfg(feature = "encoding")]
+    fn decode(encoding: &'static encoding_rs::Encoding) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Decode,
+            source: Some(ErrorSource::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("file contents are not valid {}", encoding.name()),
+            ))),
+        }
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn unknown_compression() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::UnknownCompression,
+            source: None,
+        }
+    }
+
+    fn validation_failed(source: BoxError) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::ValidationFailed,
+            source: Some(ErrorSource::Validation(source)),
+        }
+    }
+
+    fn patch_failed(source: patch::HunksFailed) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::PatchFailed,
+            source: Some(ErrorSource::Patch(source)),
+        }
+    }
+
+    #[cfg(feature = "merge")]
+    fn conflict() -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::Conflict,
+            source: None,
+        }
+    }
+
+    #[cfg(feature = "merge")]
+    fn merge_conflict(source: merge::MergeConflicts) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::MergeConflict,
+            source: Some(ErrorSource::Merge(source)),
+        }
+    }
+
+    #[cfg(feature = "toml")]
+    fn parse_toml(source: toml_edit::TomlError) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::ParseToml,
+            source: Some(ErrorSource::Toml(source)),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    fn parse_json(source: serde_json::Error) -> InPlaceError {
+        InPlaceError {
+            kind: InPlaceErrorKind::ParseJson,
+            source: Some(ErrorSource::Json(source)),
         }
     }
 }
@@ -457,23 +1665,43 @@ impl fmt::Display for InPlaceError {
 
 impl error::Error for InPlaceError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        self.source.as_ref().map(|e| {
-            let e2: &dyn error::Error = e;
-            e2
-        })
+        match self.source.as_ref()? {
+            ErrorSource::Io(e) => Some(e),
+            ErrorSource::Validation(e) => Some(&**e),
+            ErrorSource::Patch(e) => Some(e),
+            #[cfg(feature = "merge")]
+            ErrorSource::Merge(e) => Some(e),
+            #[cfg(feature = "toml")]
+            ErrorSource::Toml(e) => Some(e),
+            #[cfg(feature = "json")]
+            ErrorSource::Json(e) => Some(e),
+        }
     }
 }
 
 /// An enumeration of the operations & checks that can fail while opening,
 /// saving, or discarding an [`InPlaceFile`].
+///
+/// [`AtomicWrite::open()`], [`AtomicWriteFile::commit()`], and
+/// [`AtomicWriteFile::abort()`] can fail in the same ways as
+/// [`InPlace::open()`], [`InPlaceFile::save()`], and
+/// [`InPlaceFile::discard()`], respectively, other than in opening the edited
+/// file for reading.
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 #[non_exhaustive]
 pub enum InPlaceErrorKind {
+    /// Returned by [`InPlace::open()`] if `create_new` was set and the edited
+    /// path already exists.
+    ///
+    /// This error kind does not have a source error.
+    AlreadyExists,
+
     /// Returned by [`InPlace::open()`] if attempting to canonicalize the
     /// edited path failed.
     ///
-    /// This error kind occurs when the edited path does not exist and
-    /// `follow_symlinks` is true.
+    /// This error kind occurs when the edited path does not exist,
+    /// `follow_symlinks` is true, and neither `create` nor `create_new` is
+    /// set.
     Canonicalize,
 
     /// Returned by [`InPlace::open()`] if attempting to fetch the current
@@ -486,11 +1714,23 @@ pub enum InPlaceErrorKind {
     /// This error kind does not have a source error.
     EmptyBackup,
 
+    /// Returned by [`InPlace::for_each_path()`] and `BulkEdit::run()`
+    /// (requires the `bulk` feature) for every path if the backup specifier
+    /// is a [`Backup::Path`], as each file would be backed up to the same
+    /// path, overwriting the backup of the file before it.
+    ///
+    /// This error kind does not have a source error.
+    SharedBackup,
+
     /// Returned by [`InPlace::open()`] if attempting to fetch metadata &
     /// permission details about the edited file failed.
     ///
-    /// This error kind occurs when the edited path does not exist and
-    /// `follow_symlinks` is false.
+    /// This error kind occurs when the edited path does not exist,
+    /// `follow_symlinks` is false, and neither `create` nor `create_new` is
+    /// set.
+    ///
+    /// This error kind is also returned by [`InPlace::restore()`] if
+    /// attempting to fetch metadata about the edited file failed.
     GetMetadata,
 
     /// Returned by [`InPlace::open()`] if attempting to create the temporary
@@ -498,7 +1738,8 @@ pub enum InPlaceErrorKind {
     Mktemp,
 
     /// Returned by [`InPlace::open()`] if a [`Backup::Append`] specifier was
-    /// given and [`Path::file_name`] returned `None` for the edited path.
+    /// given or the temporary file prefix or suffix contains `{name}`, and
+    /// [`Path::file_name`] returned `None` for the edited path.
     ///
     /// This error kind does not have a source error.
     NoFilename,
@@ -513,20 +1754,168 @@ pub enum InPlaceErrorKind {
     /// for reading failed
     Open,
 
+    /// Returned by `InPlaceFile::diff()` (requires the `diff` feature) if
+    /// attempting to read the contents of the edited file or the temporary
+    /// file failed.
+    ///
+    /// This error kind is also returned by `InPlace::open_compressed()`
+    /// (requires the `gzip` or `zstd` feature) if attempting to read the
+    /// edited file's leading bytes or to set up decompression failed, and by
+    /// `InPlace::open_text()` (requires the `encoding` feature) if attempting
+    /// to read the edited file failed, and by `InPlace::replace_all()` and
+    /// `InPlace::replace_all_with()` (requires the `regex` feature) and
+    /// `InPlace::sed()` (requires the `sed` feature) if attempting to read
+    /// the edited file failed, and by [`InPlace::apply_patch()`] and
+    /// [`InPlace::apply_patch_with()`] if attempting to read the edited file
+    /// failed.  It is also returned by `InPlace::edit_toml()` (requires the
+    /// `toml` feature) if attempting to read the edited file failed or its
+    /// contents were not valid UTF-8, and by `InPlace::edit_json()` (requires
+    /// the `json` feature) if attempting to read the edited file failed.
+    ///
+    /// If `on_conflict` is set to anything other than `OnConflict::Overwrite`
+    /// (requires the `merge` feature), this error kind is also returned by
+    /// [`InPlace::open()`] if attempting to read the snapshot of the edited
+    /// file failed, and by [`InPlaceFile::save()`] if attempting to read the
+    /// edited file or the temporary file for comparison or merging failed.
+    Read,
+
+    /// Returned by `InPlace::open_text()` (requires the `encoding` feature)
+    /// if the contents of the edited file are malformed in their encoding.
+    /// The source error is an [`io::Error`] of kind
+    /// [`io::ErrorKind::InvalidData`] naming the encoding.
+    Decode,
+
+    /// Returned by `InPlace::open_compressed()` (requires the `gzip` or
+    /// `zstd` feature) if no compression format was given and the format of
+    /// the edited file could not be determined.
+    ///
+    /// This error kind does not have a source error.
+    UnknownCompression,
+
     /// Returned by [`InPlace::open()`] if attempting to copy the edited file's
-    /// permissions to the temporary file failed
+    /// permissions to the temporary file failed.
+    ///
+    /// If `defer_permissions` is set, this error kind is returned by
+    /// [`InPlaceFile::save()`] instead.
     SetMetadata,
 
+    /// Returned by [`InPlaceFile::save()`] or `InPlaceFile::diff()` (requires
+    /// the `diff` feature) if `buffered` was set and attempting to flush
+    /// buffered writes to the temporary file failed.
+    ///
+    /// This error kind is also returned by `CompressedFile::save()` (requires
+    /// the `gzip` or `zstd` feature) if attempting to finalize the compressed
+    /// output failed.
+    Flush,
+
+    /// Returned by `InPlace::replace_all()` and `InPlace::replace_all_with()`
+    /// (requires the `regex` feature) and `InPlace::sed()` (requires the
+    /// `sed` feature) if attempting to write the new contents to the
+    /// temporary file failed.
+    ///
+    /// This error kind is also returned by [`InPlace::apply_patch()`] and
+    /// [`InPlace::apply_patch_with()`] if attempting to write the patched
+    /// contents to the temporary file failed, and by [`InPlaceFile::save()`]
+    /// if `on_conflict` is set to a merging policy (requires the `merge`
+    /// feature) and attempting to write the merged contents to the temporary
+    /// file failed, and by `InPlace::edit_toml()` (requires the `toml`
+    /// feature) or `InPlace::edit_json()` (requires the `json` feature) if
+    /// attempting to write the edited document to the temporary file failed.
+    Write,
+
+    /// Returned by [`InPlace::apply_patch()`] and
+    /// [`InPlace::apply_patch_with()`] if one or more hunks of the patch
+    /// could not be applied to the edited file.  The failed hunks are
+    /// available via [`InPlaceError::failed_hunks()`], and the source error
+    /// lists them.
+    PatchFailed,
+
+    /// Returned by [`InPlaceFile::save()`] if `on_conflict` was set to
+    /// `OnConflict::Fail` (requires the `merge` feature) and the edited file
+    /// was modified after it was opened, or if `on_conflict` was set to any
+    /// other checking policy and the edited file was deleted after it was
+    /// opened.
+    ///
+    /// This error kind does not have a source error.
+    Conflict,
+
+    /// Returned by [`InPlaceFile::save()`] if `on_conflict` was set to
+    /// `OnConflict::Merge` (requires the `merge` feature), the edited file
+    /// was modified after it was opened, and the modifications conflict with
+    /// the new contents.  The conflicts are available via
+    /// `InPlaceError::merge_conflicts()`, and the source error lists the
+    /// lines of the original file at which they occur.
+    MergeConflict,
+
+    /// Returned by `InPlace::edit_toml()` (requires the `toml` feature) if
+    /// the contents of the edited file are not valid TOML.  The source error
+    /// is a `toml_edit::TomlError` describing the problem and its location.
+    ParseToml,
+
+    /// Returned by `InPlace::edit_json()` (requires the `json` feature) if
+    /// the contents of the edited file are not valid JSON.  The source error
+    /// is a `serde_json::Error` describing the problem and its location.
+    ParseJson,
+
+    /// Returned by [`InPlaceFile::save()`] if the validation function set
+    /// with [`InPlace::validate()`] returned an error.  The source error is
+    /// the error returned by the function.
+    ValidationFailed,
+
     /// Returned by [`InPlaceFile::save()`] if attempting to persist the
-    /// temporary file at the edited path failed
+    /// temporary file at the edited path failed.
+    ///
+    /// This error kind occurs when `create_new` was set and the edited path
+    /// was created after the [`InPlaceFile`] was opened.
     PersistTemp,
 
     /// Returned by [`InPlaceFile::save()`] if attempting to move the edited
-    /// file to the backup path failed
+    /// file to the backup path (or to copy it into the backup store) failed.
+    ///
+    /// This error kind is also returned by [`InPlaceFile::save_revertible()`]
+    /// if attempting to create a hidden backup failed.
     SaveBackup,
 
+    /// Returned by [`InPlace::restore()`] if no backup is set or if the
+    /// backup does not exist.
+    ///
+    /// This error kind is also returned by `InPlace::list_versions()` and
+    /// `InPlace::restore_version()` (requires the `store` feature) if the
+    /// backup is not `Backup::Store`, by `InPlace::restore_version()` if the
+    /// requested version does not exist, and by [`InPlace::restore()`] for
+    /// `Backup::Store` if no versions of the edited file are stored.
+    ///
+    /// This error kind does not have a source error.
+    NoBackup,
+
+    /// Returned by [`InPlace::restore()`] and [`InPlace::restore_saved()`]
+    /// if the edited file has been modified since the backup was made.
+    ///
+    /// This error kind does not have a source error.
+    Modified,
+
+    /// Returned by [`InPlace::restore()`] if attempting to move or copy the
+    /// backup to the edited path failed.
+    ///
+    /// This error kind is also returned by `InPlace::restore_version()`
+    /// (requires the `store` feature) if attempting to read the stored
+    /// version failed.
+    Restore,
+
+    /// Returned by `InPlace::list_versions()` and `InPlace::restore_version()`
+    /// (requires the `store` feature) if attempting to read the backup
+    /// store's index failed
+    ReadStore,
+
+    /// Returned by [`SavedFile::revert()`] if attempting to move the original
+    /// file back into place or to delete a newly-created file failed
+    Revert,
+
     /// Returned by [`InPlaceFile::discard()`] if attempting to delete the
-    /// temporary file failed
+    /// temporary file failed.
+    ///
+    /// This error kind is also returned by [`SavedFile::finalize()`] if
+    /// attempting to delete the hidden backup failed.
     Rmtemp,
 }
 
@@ -534,22 +1923,45 @@ impl InPlaceErrorKind {
     fn message(&self) -> &'static str {
         use InPlaceErrorKind::*;
         match self {
+            AlreadyExists => "file already exists",
             Canonicalize => "failed to canonicalize path",
             CurrentDir => "failed to fetch current directory",
             EmptyBackup => "backup path is empty",
+            SharedBackup => "cannot back up multiple files to the same path",
             GetMetadata => "failed to get metadata for path",
             Mktemp => "failed to create temporary file",
             NoFilename => "path does not have a filename",
             NoParent => "path does not have a parent directory",
             Open => "failed to open file for reading",
+            Read => "failed to read file contents",
+            Decode => "failed to decode file contents",
+            UnknownCompression => "could not determine compression format of file",
             SetMetadata => "failed to set metadata on temporary file",
+            Flush => "failed to flush buffered writes to temporary file",
+            Write => "failed to write to temporary file",
+            PatchFailed => "patch does not apply",
+            Conflict => "file was modified while being edited",
+            MergeConflict => "concurrent modifications to file conflict with new contents",
+            ParseToml => "failed to parse file as TOML",
+            ParseJson => "failed to parse file as JSON",
+            ValidationFailed => "validation of new file contents failed",
             PersistTemp => "failed to save temporary file at path",
             SaveBackup => "failed to move file to backup path",
+            NoBackup => "no backup to restore",
+            Modified => "file has been modified since backup was made",
+            Restore => "failed to restore file from backup",
+            ReadStore => "failed to read backup store index",
+            Revert => "failed to restore original file",
             Rmtemp => "failed to delete temporary file",
         }
     }
 }
 
+/// Returns true if nothing (not even a broken symlink) exists at `filepath`
+fn is_missing(filepath: &Path) -> bool {
+    symlink_metadata(filepath).is_err_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
 fn absolutize(filepath: &Path) -> Result<PathBuf, InPlaceError> {
     if filepath.is_absolute() {
         Ok(filepath.into())
@@ -559,26 +1971,111 @@ fn absolutize(filepath: &Path) -> Result<PathBuf, InPlaceError> {
     }
 }
 
-fn mktemp(filepath: &Path) -> Result<NamedTempFile, InPlaceError> {
-    let dirpath = filepath.parent().ok_or_else(InPlaceError::no_parent)?;
-    Builder::new()
-        .prefix("._in_place-")
-        .tempfile_in(dirpath)
-        .map_err(InPlaceError::mktemp)
+/// A validation function set with [`InPlace::validate()`].  Two
+/// `Validator`s are equal if they wrap the same function.
+#[derive(Clone)]
+struct Validator(Arc<ValidateFn>);
+
+type ValidateFn = dyn Fn(&Path) -> Result<(), BoxError> + Send + Sync;
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validator").finish_non_exhaustive()
+    }
 }
 
-fn copystats(src: &Path, dest: &File, follow_symlinks: bool) -> Result<(), InPlaceError> {
-    let md = if follow_symlinks {
-        metadata(src)
-    } else {
-        symlink_metadata(src)
+impl PartialEq for Validator {
+    fn eq(&self, other: &Validator) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Validator {}
+
+/// Settings for naming the temporary file
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct TempName {
+    prefix: OsString,
+    suffix: OsString,
+    rand_len: usize,
+}
+
+impl TempName {
+    fn mktemp(
+        &self,
+        filepath: &Path,
+        permissions: Option<Permissions>,
+    ) -> Result<NamedTempFile, InPlaceError> {
+        let dirpath = filepath.parent().ok_or_else(InPlaceError::no_parent)?;
+        let prefix = expand_name(&self.prefix, filepath)?;
+        let suffix = expand_name(&self.suffix, filepath)?;
+        let mut builder = Builder::new();
+        builder
+            .prefix(&prefix)
+            .suffix(&suffix)
+            .rand_bytes(self.rand_len);
+        if let Some(perms) = permissions {
+            builder.permissions(perms);
+        }
+        builder.tempfile_in(dirpath).map_err(InPlaceError::mktemp)
     }
-    .map_err(InPlaceError::get_metadata)?;
-    if !md.is_symlink() {
-        dest.set_permissions(md.permissions())
-            .map_err(InPlaceError::set_metadata)?;
+}
+
+impl Default for TempName {
+    fn default() -> TempName {
+        TempName {
+            prefix: OsString::from("._in_place-"),
+            suffix: OsString::new(),
+            rand_len: 6,
+        }
+    }
+}
+
+/// Replace all occurrences of `{name}` in `template` with the filename of
+/// `filepath`
+fn expand_name(template: &OsStr, filepath: &Path) -> Result<OsString, InPlaceError> {
+    let Some(s) = template.to_str().filter(|s| s.contains("{name}")) else {
+        return Ok(template.into());
+    };
+    let name = filepath.file_name().ok_or_else(InPlaceError::no_filename)?;
+    let mut expanded = OsString::new();
+    for (i, piece) in s.split("{name}").enumerate() {
+        if i > 0 {
+            expanded.push(name);
+        }
+        expanded.push(piece);
+    }
+    Ok(expanded)
+}
+
+/// Permissions & ownership to apply to the temporary file
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Stats {
+    permissions: Option<Permissions>,
+    #[cfg(unix)]
+    owner: Option<(u32, u32)>,
+}
+
+impl Stats {
+    fn apply(&self, dest: &File) -> Result<(), InPlaceError> {
+        // Ownership is copied on a best-effort basis, as unprivileged users
+        // generally can't give files away.  If we can't set both the owner &
+        // group, try to at least set the group, and ignore any failures.
+        // This is done before setting the permissions, as changing ownership
+        // may clear setuid & setgid bits.
+        #[cfg(unix)]
+        if let Some((uid, gid)) = self.owner {
+            use std::os::unix::fs::fchown;
+            if fchown(dest, Some(uid), Some(gid)).is_err() {
+                let _ = fchown(dest, None, Some(gid));
+            }
+        }
+        if let Some(perms) = self.permissions.as_ref() {
+            dest.set_permissions(perms.clone())
+                .map_err(InPlaceError::set_metadata)?;
+        }
+        Ok(())
     }
-    Ok(())
 }
 
 #[cfg(test)]
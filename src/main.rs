@@ -0,0 +1,446 @@
+//! Command-line interface for editing a file in-place by filtering it through
+//! a command, by replacing it with standard input, or by running a `sed`
+//! script over it.  Run with `--help` for usage details.
+
+#[cfg(feature = "sed")]
+use in_place::SedScript;
+use in_place::{AtomicWrite, Backup, InPlace};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::read_dir;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+static USAGE: &str = "\
+Usage: in-place [OPTIONS] FILE [--] COMMAND [ARGS ...]
+       in-place [OPTIONS] --sponge [--append] FILE
+       in-place [OPTIONS] --sed SCRIPT [-n] FILE
+
+Run COMMAND with the contents of FILE as its standard input, and replace FILE
+with COMMAND's standard output if COMMAND exits successfully.  If COMMAND
+fails, FILE is left unmodified, and in-place exits with COMMAND's exit code.
+
+With --sponge, read all of standard input and then replace FILE with it, like
+moreutils' sponge.  FILE is not examined or replaced until end of input is
+reached without error, and it is created if it does not exist.
+
+With --sed, edit FILE with SCRIPT, a script in a subset of the sed language
+supporting the s, d, p, a, i, c, h, H, g, G, x, and = commands with line
+number, $, and /REGEX/ addresses and address ranges.  Regular expressions use
+extended syntax, as with sed -E.  --sed is only available if in-place was built
+with the `sed` feature.
+
+Options:
+  --backup SUFFIX       Back up FILE by appending SUFFIX to its filename
+  --backup-path PATH    Back up FILE to PATH
+  --numbered            Back up FILE to FILE.~N~, where N is one more than the
+                        largest number used by any existing such backup
+  --no-follow-symlinks  If FILE is a symlink, replace the symlink itself
+                        rather than the file it points to
+  --sponge              Replace FILE with standard input instead of running
+                        a command
+  --append              With --sponge, append standard input to FILE instead
+                        of replacing FILE's contents
+  --sed SCRIPT          Edit FILE with a sed script instead of running a
+                        command.  If given multiple times, the scripts are
+                        joined with newlines.
+  -n, --quiet           With --sed, only output what the script prints
+                        explicitly, like sed -n
+  -h, --help            Show this help message and exit
+  -V, --version         Show the program version and exit
+";
+
+/// What the program has been asked to do
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Mode {
+    Help,
+    Version,
+    Edit(Arguments),
+}
+
+/// Options & operands for editing a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Arguments {
+    backup: Option<BackupArg>,
+    follow_symlinks: bool,
+    file: PathBuf,
+    action: Action,
+}
+
+/// How to back up the file
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum BackupArg {
+    /// Back up the file as specified by a library [`Backup`]
+    Spec(Backup),
+    /// Back up the file to `FILE.~N~`, where `N` is one more than the largest
+    /// number used by any existing such backup
+    Numbered,
+}
+
+/// How to produce the new contents of the file
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Action {
+    /// Filter the file through the given command & arguments
+    Filter(Vec<OsString>),
+    /// Replace the file with standard input, or append standard input to the
+    /// file if `append` is true
+    Sponge { append: bool },
+    /// Run the given `sed` script over the file, not printing the pattern
+    /// space automatically if `quiet` is true
+    #[cfg(feature = "sed")]
+    Sed { script: String, quiet: bool },
+}
+
+impl Mode {
+    fn from_args<I: IntoIterator<Item = OsString>>(args: I) -> Result<Mode, String> {
+        let mut args = args.into_iter();
+        let mut backup = None;
+        let mut follow_symlinks = true;
+        let mut sponge = false;
+        let mut append = false;
+        let mut sed_script: Option<String> = None;
+        let mut quiet = false;
+        let file = loop {
+            let Some(arg) = args.next() else {
+                return Err(String::from("no file specified"));
+            };
+            let Some(s) = arg.to_str() else {
+                break arg;
+            };
+            let (name, mut inline_value) = match s.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+                _ => (s, None),
+            };
+            let mut value = || match inline_value.take() {
+                Some(v) => Ok(OsString::from(v)),
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("{name} requires an argument")),
+            };
+            match name {
+                "-h" | "--help" => return Ok(Mode::Help),
+                "-V" | "--version" => return Ok(Mode::Version),
+                "--backup" => {
+                    let suffix = value()?;
+                    if suffix.is_empty() {
+                        return Err(String::from("--backup suffix cannot be empty"));
+                    }
+                    backup = Some(BackupArg::Spec(Backup::Append(suffix)));
+                }
+                "--backup-path" => {
+                    backup = Some(BackupArg::Spec(Backup::Path(value()?.into())));
+                }
+                "--numbered" => backup = Some(BackupArg::Numbered),
+                "--no-follow-symlinks" => follow_symlinks = false,
+                "--sponge" => sponge = true,
+                "--append" => append = true,
+                "--sed" => {
+                    let script = value()?
+                        .into_string()
+                        .map_err(|_| String::from("--sed script must be valid UTF-8"))?;
+                    match sed_script.as_mut() {
+                        Some(s) => {
+                            s.push('\n');
+                            s.push_str(&script);
+                        }
+                        None => sed_script = Some(script),
+                    }
+                }
+                "-n" | "--quiet" => quiet = true,
+                "--" => match args.next() {
+                    Some(file) => break file,
+                    None => return Err(String::from("no file specified")),
+                },
+                _ if name.starts_with('-') && name != "-" => {
+                    return Err(format!("unrecognized option: {name}"));
+                }
+                _ => break arg,
+            }
+            if inline_value.is_some() {
+                return Err(format!("{name} does not take an argument"));
+            }
+        };
+        let mut command = args.collect::<Vec<_>>();
+        let action = match sed_script {
+            #[cfg(not(feature = "sed"))]
+            Some(_) => {
+                return Err(String::from(
+                    "--sed is not available, as in-place was built without the `sed` feature",
+                ));
+            }
+            #[cfg(feature = "sed")]
+            Some(script) => {
+                if sponge {
+                    return Err(String::from("--sed cannot be combined with --sponge"));
+                }
+                if append {
+                    return Err(String::from("--append requires --sponge"));
+                }
+                if !command.is_empty() {
+                    return Err(String::from("--sed does not take a command"));
+                }
+                Action::Sed { script, quiet }
+            }
+            None if quiet => return Err(String::from("--quiet requires --sed")),
+            None if sponge => {
+                if !command.is_empty() {
+                    return Err(String::from("--sponge does not take a command"));
+                }
+                Action::Sponge { append }
+            }
+            None => {
+                if append {
+                    return Err(String::from("--append requires --sponge"));
+                }
+                if command.first().is_some_and(|arg| arg == "--") {
+                    command.remove(0);
+                }
+                if command.is_empty() {
+                    return Err(String::from("no command specified"));
+                }
+                Action::Filter(command)
+            }
+        };
+        Ok(Mode::Edit(Arguments {
+            backup,
+            follow_symlinks,
+            file: file.into(),
+            action,
+        }))
+    }
+}
+
+impl Arguments {
+    fn run(&self) -> Result<ExitCode, String> {
+        match &self.action {
+            Action::Filter(command) => self.filter(command),
+            Action::Sponge { append: false } => self.sponge(),
+            Action::Sponge { append: true } => self.sponge_append(),
+            #[cfg(feature = "sed")]
+            Action::Sed { script, quiet } => self.sed(script, *quiet),
+        }
+    }
+
+    fn inplace(&self) -> Result<InPlace, String> {
+        let mut inp = InPlace::new(&self.file);
+        if let Some(backup) = self.backup()? {
+            inp.backup(backup);
+        }
+        inp.follow_symlinks(self.follow_symlinks);
+        Ok(inp)
+    }
+
+    /// Determine the library backup specifier to use, if any.  Numbered
+    /// backups are numbered at the time this is called.
+    fn backup(&self) -> Result<Option<Backup>, String> {
+        match &self.backup {
+            None => Ok(None),
+            Some(BackupArg::Spec(backup)) => Ok(Some(backup.clone())),
+            Some(BackupArg::Numbered) => self.numbered_backup().map(Some),
+        }
+    }
+
+    /// Compute the filename of the next numbered backup of the file, taking
+    /// into account symlink resolution in the same way as the library
+    fn numbered_backup(&self) -> Result<Backup, String> {
+        let path = if self.follow_symlinks {
+            self.file
+                .canonicalize()
+                .unwrap_or_else(|_| self.file.clone())
+        } else {
+            self.file.clone()
+        };
+        let fname = path
+            .file_name()
+            .ok_or_else(|| format!("{}: path does not have a filename", self.file.display()))?;
+        let dirpath = match path.parent() {
+            Some(p) if p != Path::new("") => p,
+            _ => Path::new("."),
+        };
+        let mut maxnum = 0;
+        let entries = read_dir(dirpath)
+            .map_err(|e| format!("failed to read {}: {}", dirpath.display(), chain(&e)))?;
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| format!("failed to read {}: {}", dirpath.display(), chain(&e)))?;
+            let name = entry.file_name();
+            let n = name
+                .as_encoded_bytes()
+                .strip_prefix(fname.as_encoded_bytes())
+                .and_then(|s| s.strip_prefix(b".~"))
+                .and_then(|s| s.strip_suffix(b"~"))
+                .filter(|s| !s.is_empty() && s.iter().all(u8::is_ascii_digit))
+                .and_then(|s| std::str::from_utf8(s).ok())
+                .and_then(|s| s.parse::<u64>().ok());
+            if let Some(n) = n {
+                maxnum = maxnum.max(n);
+            }
+        }
+        let mut backup_name = fname.to_os_string();
+        backup_name.push(format!(".~{}~", maxnum + 1));
+        Ok(Backup::FileName(backup_name))
+    }
+
+    fn filter(&self, command: &[OsString]) -> Result<ExitCode, String> {
+        let inp = self
+            .inplace()?
+            .open()
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        let stdin = inp
+            .reader()
+            .try_clone()
+            .map_err(|e| format!("failed to duplicate file handle: {}", chain(&e)))?;
+        let stdout = inp
+            .writer()
+            .try_clone()
+            .map_err(|e| format!("failed to duplicate file handle: {}", chain(&e)))?;
+        let (cmd, cmdargs) = command
+            .split_first()
+            .ok_or_else(|| String::from("no command specified"))?;
+        let r = Command::new(cmd)
+            .args(cmdargs)
+            .stdin(stdin)
+            .stdout(stdout)
+            .status();
+        match r {
+            Ok(status) if status.success() => {
+                inp.save()
+                    .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Ok(status) => {
+                inp.discard()
+                    .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+                eprintln!(
+                    "in-place: {} {status}; not modifying {}",
+                    cmd.to_string_lossy(),
+                    self.file.display()
+                );
+                Ok(status
+                    .code()
+                    .and_then(|c| u8::try_from(c).ok())
+                    .map_or(ExitCode::FAILURE, ExitCode::from))
+            }
+            Err(e) => {
+                let _ = inp.discard();
+                Err(format!(
+                    "failed to run {}: {}",
+                    cmd.to_string_lossy(),
+                    chain(&e)
+                ))
+            }
+        }
+    }
+
+    fn sponge(&self) -> Result<ExitCode, String> {
+        let input = read_stdin()?;
+        let mut aw = AtomicWrite::new(&self.file);
+        if let Some(backup) = self.backup()? {
+            aw.backup(backup);
+        }
+        let aw = aw
+            .follow_symlinks(self.follow_symlinks)
+            .open()
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        if let Err(e) = aw.writer().write_all(&input) {
+            let _ = aw.abort();
+            return Err(format!(
+                "failed to write to temporary file for {}: {}",
+                self.file.display(),
+                chain(&e)
+            ));
+        }
+        aw.commit()
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn sponge_append(&self) -> Result<ExitCode, String> {
+        let input = read_stdin()?;
+        let inp = self
+            .inplace()?
+            .create(true)
+            .open()
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        let r = io::copy(&mut inp.reader(), &mut inp.writer())
+            .map_err(|e| format!("failed to copy {}: {}", self.file.display(), chain(&e)))
+            .and_then(|_| {
+                inp.writer().write_all(&input).map_err(|e| {
+                    format!(
+                        "failed to write to temporary file for {}: {}",
+                        self.file.display(),
+                        chain(&e)
+                    )
+                })
+            });
+        if let Err(msg) = r {
+            let _ = inp.discard();
+            return Err(msg);
+        }
+        inp.save()
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    #[cfg(feature = "sed")]
+    fn sed(&self, script: &str, quiet: bool) -> Result<ExitCode, String> {
+        let mut script =
+            SedScript::parse(script).map_err(|e| format!("invalid sed script: {e}"))?;
+        if quiet {
+            script.quiet(true);
+        }
+        self.inplace()?
+            .sed(&script)
+            .map_err(|e| format!("{}: {}", self.file.display(), chain(&e)))?;
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Read all of standard input into memory, so that the edited file is not
+/// touched or examined until end of input is reached without error
+fn read_stdin() -> Result<Vec<u8>, String> {
+    let mut input = Vec::new();
+    io::stdin()
+        .lock()
+        .read_to_end(&mut input)
+        .map_err(|e| format!("failed to read standard input: {}", chain(&e)))?;
+    Ok(input)
+}
+
+/// Format an error along with all of its sources
+fn chain(e: &dyn Error) -> String {
+    let mut s = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        s.push_str(": ");
+        s.push_str(&e.to_string());
+        source = e.source();
+    }
+    s
+}
+
+fn main() -> ExitCode {
+    match Mode::from_args(std::env::args_os().skip(1)) {
+        Ok(Mode::Help) => {
+            print!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Ok(Mode::Version) => {
+            println!("in-place {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Ok(Mode::Edit(args)) => match args.run() {
+            Ok(code) => code,
+            Err(msg) => {
+                eprintln!("in-place: {msg}");
+                ExitCode::FAILURE
+            }
+        },
+        Err(msg) => {
+            eprintln!("in-place: {msg}");
+            eprintln!("Run `in-place --help` for usage information.");
+            ExitCode::from(2)
+        }
+    }
+}
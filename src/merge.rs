@@ -0,0 +1,343 @@
+use crate::{InPlaceError, Output};
+use similar::{Algorithm, DiffOp, capture_diff_slices};
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// What [`InPlaceFile::save()`] should do if the edited file was modified by
+/// someone else after it was opened.  See [`InPlace::on_conflict()`].
+///
+/// Modification is detected by comparing the contents of the edited path at
+/// save time with a snapshot of the contents read when the file was opened.
+/// For [`OnConflict::Fail`], only the length and a hash of the contents are
+/// kept; the merging policies keep the full contents in memory.
+/// The check is made immediately before the edited file is backed up and
+/// replaced, and so a modification made in between is not detected.
+///
+/// Merging is done line by line on the raw bytes of the files, with lines
+/// terminated by LF.  When an edited file is modified and merged, the backup
+/// (if any) is made of the modified file rather than of the snapshot.
+///
+/// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+/// [`InPlace::on_conflict()`]: crate::InPlace::on_conflict
+#[cfg_attr(docsrs, doc(cfg(feature = "merge")))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum OnConflict {
+    /// Do not check for modifications, and replace the edited file with the
+    /// new contents regardless.  No snapshot of the original contents is
+    /// kept.
+    #[default]
+    Overwrite,
+
+    /// Fail with an error of kind [`InPlaceErrorKind::Conflict`] and leave
+    /// the edited file unmodified
+    ///
+    /// [`InPlaceErrorKind::Conflict`]: crate::InPlaceErrorKind::Conflict
+    Fail,
+
+    /// Perform a three-way merge of the original contents, the new contents,
+    /// and the modified contents of the edited file.  If the changes merge
+    /// cleanly, save the merged contents; otherwise, fail with an error of
+    /// kind [`InPlaceErrorKind::MergeConflict`] and leave the edited file
+    /// unmodified.
+    ///
+    /// [`InPlaceErrorKind::MergeConflict`]: crate::InPlaceErrorKind::MergeConflict
+    Merge,
+
+    /// Perform a three-way merge as with [`OnConflict::Merge`], but save the
+    /// merged contents even if there are conflicts, with each conflicting
+    /// region written between Git-style conflict markers: the new lines
+    /// after `<<<<<<< ours`, and the lines from the modified file after
+    /// `=======` and before `>>>>>>> theirs`.
+    MergeWithMarkers,
+}
+
+/// A snapshot of the contents of the edited file taken when it was opened,
+/// for detecting modifications on save
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Snapshot {
+    policy: OnConflict,
+    contents: Contents,
+}
+
+impl Snapshot {
+    /// Read all of `reader` and then rewind it to the start.  The full
+    /// contents are only kept if they may be needed for merging.
+    pub(crate) fn take(policy: OnConflict, mut reader: &File) -> Result<Snapshot, InPlaceError> {
+        let contents = if policy == OnConflict::Fail {
+            Digest::of(reader).map(Contents::Digest)
+        } else {
+            let mut content = Vec::new();
+            reader
+                .read_to_end(&mut content)
+                .map(|_| Contents::Full(content))
+        };
+        let contents = contents
+            .and_then(|c| reader.seek(SeekFrom::Start(0)).map(|_| c))
+            .map_err(InPlaceError::read)?;
+        Ok(Snapshot { policy, contents })
+    }
+}
+
+/// The contents of the edited file as recorded in a [`Snapshot`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Contents {
+    Full(Vec<u8>),
+    Digest(Digest),
+}
+
+/// The length and hash of a file's contents
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Digest {
+    len: u64,
+    hash: u64,
+}
+
+impl Digest {
+    /// Compute the digest of everything read from `reader`
+    fn of<R: Read>(mut reader: R) -> io::Result<Digest> {
+        let mut hasher = DefaultHasher::new();
+        let mut len = 0;
+        let mut buf = [0; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    hasher.write(&buf[..n]);
+                    len += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Digest {
+            len,
+            hash: hasher.finish(),
+        })
+    }
+}
+
+impl Output {
+    /// Fail on modifications of the edited file instead of merging them, for
+    /// outputs whose contents cannot be merged line by line
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub(crate) fn without_merge(mut self) -> Output {
+        if let Some(snapshot) = self.snapshot.as_mut() {
+            if snapshot.policy != OnConflict::Overwrite {
+                snapshot.policy = OnConflict::Fail;
+            }
+        }
+        self
+    }
+
+    /// If the edited file has been modified since it was opened, handle the
+    /// modification according to the conflict policy, merging it into the
+    /// temporary file if so configured
+    pub(crate) fn check_conflict(&self) -> Result<(), InPlaceError> {
+        let Some(snapshot) = self.snapshot.as_ref() else {
+            return Ok(());
+        };
+        let mut current = match File::open(&self.path) {
+            Ok(fp) => fp,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if self.existed {
+                    // The edited file was deleted, which cannot be merged
+                    return Err(InPlaceError::conflict());
+                }
+                return Ok(());
+            }
+            Err(e) => return Err(InPlaceError::read(e)),
+        };
+        let base = match &snapshot.contents {
+            Contents::Full(base) => base,
+            Contents::Digest(digest) => {
+                let unchanged = Digest::of(current).map_err(InPlaceError::read)? == *digest;
+                return if self.existed && unchanged {
+                    Ok(())
+                } else {
+                    Err(InPlaceError::conflict())
+                };
+            }
+        };
+        let mut theirs = Vec::new();
+        current
+            .read_to_end(&mut theirs)
+            .map_err(InPlaceError::read)?;
+        if self.existed && theirs == *base {
+            return Ok(());
+        }
+        let markers = match snapshot.policy {
+            OnConflict::Overwrite => return Ok(()),
+            OnConflict::Fail => return Err(InPlaceError::conflict()),
+            OnConflict::Merge => false,
+            OnConflict::MergeWithMarkers => true,
+        };
+        let mut temp = self.writer.as_file();
+        let mut ours = Vec::new();
+        temp.seek(SeekFrom::Start(0))
+            .and_then(|_| temp.read_to_end(&mut ours))
+            .map_err(InPlaceError::read)?;
+        let merged = merge3(base, &ours, &theirs);
+        if !merged.conflicts.is_empty() && !markers {
+            return Err(InPlaceError::merge_conflict(MergeConflicts(
+                merged.conflicts,
+            )));
+        }
+        temp.set_len(0)
+            .and_then(|()| temp.seek(SeekFrom::Start(0)))
+            .and_then(|_| temp.write_all(&merged.content))
+            .map_err(InPlaceError::write)
+    }
+}
+
+/// The source error of an [`InPlaceError`] of kind
+/// [`InPlaceErrorKind::MergeConflict`], giving the lines of the original file
+/// at which conflicting changes were made
+///
+/// [`InPlaceErrorKind::MergeConflict`]: crate::InPlaceErrorKind::MergeConflict
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct MergeConflicts(pub(crate) Vec<Conflict>);
+
+impl fmt::Display for MergeConflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "conflicting changes at")?;
+        for (i, c) in self.0.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            if c.len > 1 {
+                write!(f, "{sep} lines {}-{}", c.line, c.line + c.len - 1)?;
+            } else {
+                write!(f, "{sep} line {}", c.line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for MergeConflicts {}
+
+/// A region of the edited file in which the new contents and the
+/// modifications made by someone else conflict.  See
+/// [`InPlaceError::merge_conflicts()`].
+///
+/// [`InPlaceError::merge_conflicts()`]: crate::InPlaceError::merge_conflicts
+#[cfg_attr(docsrs, doc(cfg(feature = "merge")))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Conflict {
+    line: usize,
+    len: usize,
+}
+
+impl Conflict {
+    /// The line of the original file at which the region starts, starting
+    /// from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The number of lines of the original file that the region covers.  This
+    /// is zero if both sides inserted different lines at the same position.
+    pub fn line_count(&self) -> usize {
+        self.len
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Merged {
+    content: Vec<u8>,
+    conflicts: Vec<Conflict>,
+}
+
+/// Perform a line-based three-way merge of the changes from `base` to `ours`
+/// and from `base` to `theirs`.  Conflicting regions are written with
+/// conflict markers.
+fn merge3(base: &[u8], ours: &[u8], theirs: &[u8]) -> Merged {
+    let base = split_lines(base);
+    let ours = split_lines(ours);
+    let theirs = split_lines(theirs);
+    let to_ours = matching(&base, &ours);
+    let to_theirs = matching(&base, &theirs);
+    let mut content = Vec::new();
+    let mut conflicts = Vec::new();
+    let (mut o, mut a, mut b) = (0, 0, 0);
+    loop {
+        // Copy the lines that are unchanged on both sides
+        while o < base.len() && to_ours[o] == Some(a) && to_theirs[o] == Some(b) {
+            content.extend_from_slice(base[o]);
+            o += 1;
+            a += 1;
+            b += 1;
+        }
+        // Find the next base line that is unchanged on both sides; everything
+        // before it has been changed on at least one side
+        let (next_o, next_a, next_b) = (o..base.len())
+            .find_map(|i| Some((i, to_ours[i]?, to_theirs[i]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        if (next_o, next_a, next_b) == (o, a, b) {
+            break;
+        }
+        let base_chunk = &base[o..next_o];
+        let ours_chunk = &ours[a..next_a];
+        let theirs_chunk = &theirs[b..next_b];
+        if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+            for ln in theirs_chunk {
+                content.extend_from_slice(ln);
+            }
+        } else if theirs_chunk == base_chunk {
+            for ln in ours_chunk {
+                content.extend_from_slice(ln);
+            }
+        } else {
+            conflicts.push(Conflict {
+                line: o + 1,
+                len: next_o - o,
+            });
+            write_lines(&mut content, b"<<<<<<< ours\n", ours_chunk);
+            write_lines(&mut content, b"=======\n", theirs_chunk);
+            write_lines(&mut content, b">>>>>>> theirs\n", &[]);
+        }
+        (o, a, b) = (next_o, next_a, next_b);
+    }
+    Merged { content, conflicts }
+}
+
+/// Split `content` into lines, each including its terminating LF (if any)
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// For each line of `old`, determine the index of the line of `new` that it
+/// is matched to by a diff, if any
+fn matching(old: &[&[u8]], new: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; old.len()];
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            for i in 0..len {
+                matches[old_index + i] = Some(new_index + i);
+            }
+        }
+    }
+    matches
+}
+
+/// Write `marker` to `content`, followed by `lines`, making sure that the
+/// marker starts on a line of its own
+fn write_lines(content: &mut Vec<u8>, marker: &[u8], lines: &[&[u8]]) {
+    if content.last().is_some_and(|&b| b != b'\n') {
+        content.push(b'\n');
+    }
+    content.extend_from_slice(marker);
+    for ln in lines {
+        content.extend_from_slice(ln);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,110 @@
+use super::{Conflict, MergeConflicts, merge3};
+
+const BASE: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+fn merge(ours: &str, theirs: &str) -> (String, Vec<Conflict>) {
+    let merged = merge3(BASE.as_bytes(), ours.as_bytes(), theirs.as_bytes());
+    (
+        String::from_utf8(merged.content).expect("merge should be UTF-8"),
+        merged.conflicts,
+    )
+}
+
+#[test]
+fn merge_unchanged() {
+    assert_eq!(merge(BASE, BASE), (BASE.to_owned(), Vec::new()));
+}
+
+#[test]
+fn merge_one_side() {
+    let changed = "one\nTWO\nthree\nfour\nfive\nsix\n";
+    assert_eq!(merge(changed, BASE), (changed.to_owned(), Vec::new()));
+    assert_eq!(merge(BASE, changed), (changed.to_owned(), Vec::new()));
+}
+
+#[test]
+fn merge_both_sides() {
+    let ours = "zero\none\ntwo\nTHREE\nfour\nfive\n";
+    let theirs = "one\ntwo\nthree\nfour\nFIVE\nsix\n";
+    assert_eq!(
+        merge(ours, theirs),
+        (
+            String::from("zero\none\ntwo\nTHREE\nfour\nFIVE\nsix\n"),
+            Vec::new()
+        )
+    );
+}
+
+#[test]
+fn merge_same_change() {
+    let changed = "one\ntwo\n3\nfour\nfive\n";
+    assert_eq!(merge(changed, changed), (changed.to_owned(), Vec::new()));
+}
+
+#[test]
+fn merge_deletions() {
+    let ours = "two\nthree\nfour\nfive\n";
+    let theirs = "one\ntwo\nthree\nfive\n";
+    assert_eq!(
+        merge(ours, theirs),
+        (String::from("two\nthree\nfive\n"), Vec::new())
+    );
+}
+
+#[test]
+fn merge_conflict() {
+    let ours = "one\nTWO\nTHREE\nfour\nfive\n";
+    let theirs = "one\n2\n3\nfour\nFIVE\n";
+    let (content, conflicts) = merge(ours, theirs);
+    assert_eq!(
+        content,
+        concat!(
+            "one\n",
+            "<<<<<<< ours\n",
+            "TWO\n",
+            "THREE\n",
+            "=======\n",
+            "2\n",
+            "3\n",
+            ">>>>>>> theirs\n",
+            "four\n",
+            "FIVE\n",
+        )
+    );
+    assert_eq!(conflicts, [Conflict { line: 2, len: 2 }]);
+}
+
+#[test]
+fn merge_conflict_without_final_newline() {
+    let merged = merge3(b"a\nb", b"a\nc", b"a\nd");
+    assert_eq!(
+        merged.content,
+        b"a\n<<<<<<< ours\nc\n=======\nd\n>>>>>>> theirs\n"
+    );
+    assert_eq!(merged.conflicts, [Conflict { line: 2, len: 1 }]);
+}
+
+#[test]
+fn merge_conflicting_insertions() {
+    let ours = "one\ntwo\nthree\nfour\nfive\nsix\n";
+    let theirs = "one\ntwo\nthree\nfour\nfive\n6\n";
+    let (content, conflicts) = merge(ours, theirs);
+    assert_eq!(
+        content,
+        "one\ntwo\nthree\nfour\nfive\n<<<<<<< ours\nsix\n=======\n6\n>>>>>>> theirs\n"
+    );
+    assert_eq!(conflicts, [Conflict { line: 6, len: 0 }]);
+}
+
+#[test]
+fn display_conflicts() {
+    let e = MergeConflicts(vec![
+        Conflict { line: 2, len: 3 },
+        Conflict { line: 7, len: 1 },
+        Conflict { line: 10, len: 0 },
+    ]);
+    assert_eq!(
+        e.to_string(),
+        "conflicting changes at lines 2-4, line 7, line 10"
+    );
+}
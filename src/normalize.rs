@@ -0,0 +1,217 @@
+use std::io::{self, Write};
+
+/// A writer adapter that fixes up line endings, trailing whitespace, and
+/// trailing newlines in text as it is written.
+///
+/// A `NormalizeWriter` wraps another writer (typically
+/// [`InPlaceFile::writer()`] or an `&InPlaceFile`) and applies the
+/// configured policies while streaming, holding back only the whitespace and
+/// line terminators that it cannot yet decide what to do with.  Once all data
+/// has been written, [`NormalizeWriter::finish()`] must be called to apply
+/// the policies to the end of the text and write out anything held back;
+/// dropping a `NormalizeWriter` without calling `finish()` loses that data.
+///
+/// Line terminators are LF, CR LF, and lone CR.  Whitespace is space and tab.
+/// Text is processed byte by byte, and so any ASCII-compatible encoding
+/// (including UTF-8) is supported.
+///
+/// By default, a `NormalizeWriter` passes all data through unchanged.
+///
+/// # Example
+///
+/// ```no_run
+/// use in_place::{InPlace, LineEnding, NormalizeWriter, TrailingNewlines};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let inp = InPlace::new("somefile.txt").open()?;
+///     let mut writer = NormalizeWriter::new(inp.writer());
+///     writer
+///         .line_ending(LineEnding::Lf)
+///         .strip_trailing_whitespace(true)
+///         .trailing_newlines(TrailingNewlines::One);
+///     std::io::copy(&mut inp.reader(), &mut writer)?;
+///     writer.finish()?;
+///     inp.save()?;
+///     Ok(())
+/// }
+/// ```
+///
+/// [`InPlaceFile::writer()`]: crate::InPlaceFile::writer
+#[derive(Debug)]
+pub struct NormalizeWriter<W: Write> {
+    inner: W,
+    line_ending: Option<LineEnding>,
+    strip_trailing_whitespace: bool,
+    trailing_newlines: TrailingNewlines,
+    /// Whether the last byte written was a CR that may be part of a CR LF
+    pending_cr: bool,
+    /// Whitespace at the end of the current line that will be dropped if the
+    /// line ends before any other content
+    pending_ws: Vec<u8>,
+    /// Line terminators that have not yet been written, as they may turn out
+    /// to be trailing newlines
+    pending_newlines: Vec<u8>,
+    /// The first line terminator written, used when adding a missing final
+    /// newline while preserving line endings
+    first_terminator: Option<&'static [u8]>,
+    /// Whether any content other than line terminators has been written
+    has_content: bool,
+}
+
+/// A line terminator to convert all line endings to.  See
+/// [`NormalizeWriter::line_ending()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum LineEnding {
+    /// LF (`"\n"`), as used on Unix
+    Lf,
+    /// CR LF (`"\r\n"`), as used on Windows
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// What to do with line terminators at the end of the text.  See
+/// [`NormalizeWriter::trailing_newlines()`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum TrailingNewlines {
+    /// Leave trailing newlines as they are
+    #[default]
+    Keep,
+    /// Ensure that nonempty text ends with exactly one newline, adding one if
+    /// missing and removing any trailing blank lines.  Text consisting only
+    /// of line terminators (and stripped whitespace) becomes empty.
+    One,
+    /// Remove all newlines from the end of the text
+    Strip,
+}
+
+impl<W: Write> NormalizeWriter<W> {
+    /// Create a new `NormalizeWriter` wrapping the given writer
+    pub fn new(inner: W) -> NormalizeWriter<W> {
+        NormalizeWriter {
+            inner,
+            line_ending: None,
+            strip_trailing_whitespace: false,
+            trailing_newlines: TrailingNewlines::Keep,
+            pending_cr: false,
+            pending_ws: Vec::new(),
+            pending_newlines: Vec::new(),
+            first_terminator: None,
+            has_content: false,
+        }
+    }
+
+    /// Convert all line terminators to the given line ending.  By default,
+    /// line terminators are left as they are.
+    pub fn line_ending(&mut self, ending: LineEnding) -> &mut Self {
+        self.line_ending = Some(ending);
+        self
+    }
+
+    /// If `flag` is true, spaces and tabs at the end of each line (including
+    /// the last line) are removed.  The default is false.
+    pub fn strip_trailing_whitespace(&mut self, flag: bool) -> &mut Self {
+        self.strip_trailing_whitespace = flag;
+        self
+    }
+
+    /// Set the policy for line terminators at the end of the text.  The
+    /// default is [`TrailingNewlines::Keep`].
+    pub fn trailing_newlines(&mut self, policy: TrailingNewlines) -> &mut Self {
+        self.trailing_newlines = policy;
+        self
+    }
+
+    /// A reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Apply the policies to the end of the text, write out any data held
+    /// back, flush, and return the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Fails if writing to or flushing the inner writer fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut out = Vec::new();
+        if std::mem::take(&mut self.pending_cr) {
+            self.newline(b"\r", &mut out);
+        }
+        self.pending_ws.clear();
+        match self.trailing_newlines {
+            TrailingNewlines::Keep => out.append(&mut self.pending_newlines),
+            TrailingNewlines::One if self.has_content => {
+                let term = self
+                    .line_ending
+                    .map(LineEnding::as_bytes)
+                    .or(self.first_terminator)
+                    .unwrap_or(b"\n");
+                out.extend_from_slice(term);
+            }
+            TrailingNewlines::One | TrailingNewlines::Strip => (),
+        }
+        self.inner.write_all(&out)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Handle the end of a line terminated by `term`
+    fn newline(&mut self, term: &'static [u8], out: &mut Vec<u8>) {
+        self.pending_ws.clear();
+        let term = self.line_ending.map_or(term, LineEnding::as_bytes);
+        self.first_terminator.get_or_insert(term);
+        if self.trailing_newlines == TrailingNewlines::Keep {
+            out.extend_from_slice(term);
+        } else {
+            self.pending_newlines.extend_from_slice(term);
+        }
+    }
+}
+
+impl<W: Write> Write for NormalizeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len());
+        for &b in buf {
+            if std::mem::take(&mut self.pending_cr) {
+                if b == b'\n' {
+                    self.newline(b"\r\n", &mut out);
+                    continue;
+                }
+                self.newline(b"\r", &mut out);
+            }
+            match b {
+                b'\r' => self.pending_cr = true,
+                b'\n' => self.newline(b"\n", &mut out),
+                b' ' | b'\t' if self.strip_trailing_whitespace => self.pending_ws.push(b),
+                _ => {
+                    out.append(&mut self.pending_newlines);
+                    out.append(&mut self.pending_ws);
+                    out.push(b);
+                    self.has_content = true;
+                }
+            }
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    /// Flush the inner writer.  Data held back by the `NormalizeWriter` is
+    /// not written until more data is written or
+    /// [`NormalizeWriter::finish()`] is called.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,92 @@
+use super::{LineEnding, NormalizeWriter, TrailingNewlines};
+use std::io::Write;
+
+fn normalize(chunks: &[&[u8]], configure: impl FnOnce(&mut NormalizeWriter<Vec<u8>>)) -> Vec<u8> {
+    let mut writer = NormalizeWriter::new(Vec::new());
+    configure(&mut writer);
+    for chunk in chunks {
+        writer
+            .write_all(chunk)
+            .expect("writing to a Vec should not fail");
+    }
+    writer.finish().expect("writing to a Vec should not fail")
+}
+
+#[test]
+fn passthrough() {
+    let text: &[u8] = b"foo  \r\nbar\rbaz\n\n\n";
+    assert_eq!(normalize(&[text], |_| ()), text);
+}
+
+#[test]
+fn crlf_to_lf() {
+    let out = normalize(&[b"foo\r", b"\nbar\r\nbaz\rquux"], |w| {
+        w.line_ending(LineEnding::Lf);
+    });
+    assert_eq!(out, b"foo\nbar\nbaz\nquux");
+}
+
+#[test]
+fn lf_to_crlf() {
+    let out = normalize(&[b"foo\nbar\r\nbaz\n"], |w| {
+        w.line_ending(LineEnding::CrLf);
+    });
+    assert_eq!(out, b"foo\r\nbar\r\nbaz\r\n");
+}
+
+#[test]
+fn trailing_cr_at_end() {
+    let out = normalize(&[b"foo\r"], |w| {
+        w.line_ending(LineEnding::Lf);
+    });
+    assert_eq!(out, b"foo\n");
+}
+
+#[test]
+fn strip_trailing_whitespace() {
+    let out = normalize(&[b"foo \t", b" \nbar baz\t\r\n  \nquux  "], |w| {
+        w.strip_trailing_whitespace(true);
+    });
+    assert_eq!(out, b"foo\nbar baz\r\n\nquux");
+}
+
+#[test]
+fn one_trailing_newline_added() {
+    let out = normalize(&[b"foo\r\nbar"], |w| {
+        w.trailing_newlines(TrailingNewlines::One);
+    });
+    assert_eq!(out, b"foo\r\nbar\r\n");
+}
+
+#[test]
+fn one_trailing_newline_collapsed() {
+    let out = normalize(&[b"foo\n\nbar\n", b"\n  \n\n"], |w| {
+        w.trailing_newlines(TrailingNewlines::One)
+            .strip_trailing_whitespace(true);
+    });
+    assert_eq!(out, b"foo\n\nbar\n");
+}
+
+#[test]
+fn one_trailing_newline_empty() {
+    let out = normalize(&[b"\n\n"], |w| {
+        w.trailing_newlines(TrailingNewlines::One);
+    });
+    assert_eq!(out, b"");
+}
+
+#[test]
+fn strip_trailing_newlines() {
+    let out = normalize(&[b"foo\nbar\n\n", b"\r\n"], |w| {
+        w.trailing_newlines(TrailingNewlines::Strip);
+    });
+    assert_eq!(out, b"foo\nbar");
+}
+
+#[test]
+fn whitespace_only_line_is_content() {
+    let out = normalize(&[b"foo\n  \n\n"], |w| {
+        w.trailing_newlines(TrailingNewlines::One);
+    });
+    assert_eq!(out, b"foo\n  \n");
+}
@@ -0,0 +1,619 @@
+use crate::{InPlace, InPlaceError};
+use std::error;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// A parsed unified diff for a single file, for applying to the edited file
+/// with [`InPlace::apply_patch()`].
+///
+/// Patches are parsed from the output of `diff -u`, `git diff`, and similar
+/// tools.  Any lines before the `---`/`+++` file header or between hunks that
+/// are not part of a hunk (such as `diff --git` or `index` lines) are ignored.
+/// The file header may be omitted, in which case the patch has no paths.
+/// Lines are compared byte-for-byte, including any trailing carriage returns,
+/// and `\ No newline at end of file` markers are honored when writing the new
+/// contents.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Patch {
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl Patch {
+    /// Parse a unified diff containing changes to a single file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the diff is malformed, contains no hunks, or
+    /// contains changes to more than one file.
+    pub fn parse(diff: &str) -> Result<Patch, PatchParseError> {
+        let mut patches = parse_patches(diff)?.into_iter();
+        match (patches.next(), patches.next()) {
+            (Some((_, patch)), None) => Ok(patch),
+            (_, Some((line, _))) => Err(PatchParseError {
+                line,
+                message: String::from("diff contains changes to more than one file"),
+            }),
+            (None, None) => Err(no_hunks(1)),
+        }
+    }
+
+    /// Parse a unified diff containing changes to one or more files,
+    /// returning a `Patch` for each file in the order in which they appear
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the diff is malformed, contains no hunks, or
+    /// contains a file header without any hunks.
+    pub fn parse_all(diff: &str) -> Result<Vec<Patch>, PatchParseError> {
+        Ok(parse_patches(diff)?
+            .into_iter()
+            .map(|(_, patch)| patch)
+            .collect())
+    }
+
+    /// The path on the `---` line of the file header, up to any tab, or
+    /// `None` if there was no header or the path is `/dev/null`.  Any prefix
+    /// such as `a/` is kept.
+    pub fn old_path(&self) -> Option<&str> {
+        self.old_path.as_deref()
+    }
+
+    /// The path on the `+++` line of the file header, up to any tab, or
+    /// `None` if there was no header or the path is `/dev/null`.  Any prefix
+    /// such as `b/` is kept.
+    pub fn new_path(&self) -> Option<&str> {
+        self.new_path.as_deref()
+    }
+
+    /// The number of hunks in the patch
+    pub fn hunk_count(&self) -> usize {
+        self.hunks.len()
+    }
+
+    /// Apply the patch to `content`, returning the new contents and the
+    /// locations at which the hunks were applied
+    fn apply_to(
+        &self,
+        content: &[u8],
+        max_fuzz: usize,
+    ) -> Result<(Vec<u8>, Vec<AppliedHunk>), HunksFailed> {
+        let lines = split_lines(content);
+        let mut out = Vec::with_capacity(lines.len());
+        let mut cursor = 0;
+        let mut offset = 0;
+        let mut applied = Vec::new();
+        let mut failed = Vec::new();
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let number = i + 1;
+            let Some(loc) = hunk.locate(&lines, cursor, offset, max_fuzz) else {
+                failed.push(FailedHunk {
+                    number,
+                    line: hunk.old_start,
+                });
+                continue;
+            };
+            out.extend_from_slice(&lines[cursor..loc.start]);
+            hunk.emit(loc.lead, loc.trail, &mut out);
+            cursor = loc.start + hunk.old_lines().count() - loc.lead - loc.trail;
+            offset = signed(loc.start) - signed(hunk.base() + loc.lead);
+            applied.push(AppliedHunk {
+                number,
+                line: loc.start + 1,
+                offset,
+                fuzz: loc.fuzz,
+            });
+        }
+        if !failed.is_empty() {
+            return Err(HunksFailed(failed));
+        }
+        out.extend_from_slice(&lines[cursor..]);
+        let mut buf = Vec::with_capacity(content.len());
+        let n = out.len();
+        for (i, (text, newline)) in out.into_iter().enumerate() {
+            buf.extend_from_slice(text);
+            if newline || i + 1 < n {
+                buf.push(b'\n');
+            }
+        }
+        Ok((buf, applied))
+    }
+}
+
+/// Options for [`InPlace::apply_patch_with()`]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PatchOptions {
+    fuzz: usize,
+}
+
+impl PatchOptions {
+    /// Create a new `PatchOptions` with the default settings: a fuzz factor
+    /// of 2
+    pub fn new() -> PatchOptions {
+        PatchOptions::default()
+    }
+
+    /// Set the fuzz factor, the maximum number of leading and trailing
+    /// context lines of a hunk that may be ignored when looking for a place
+    /// to apply it, as with the `--fuzz` option of `patch`.  The default is
+    /// 2.  A fuzz factor of 0 requires all context lines to match.
+    pub fn fuzz(&mut self, lines: usize) -> &mut Self {
+        self.fuzz = lines;
+        self
+    }
+}
+
+impl Default for PatchOptions {
+    fn default() -> PatchOptions {
+        PatchOptions { fuzz: 2 }
+    }
+}
+
+impl InPlace {
+    /// Apply `patch` to the edited file with the default [`PatchOptions`] and
+    /// return where each hunk was applied.  See
+    /// [`InPlace::apply_patch_with()`].
+    ///
+    /// # Errors
+    ///
+    /// See [`InPlace::apply_patch_with()`].
+    pub fn apply_patch(&self, patch: &Patch) -> Result<Vec<AppliedHunk>, InPlaceError> {
+        self.apply_patch_with(patch, &PatchOptions::new())
+    }
+
+    /// Apply `patch` to the edited file as configured by `options` and return
+    /// where each hunk was applied.
+    ///
+    /// The edited file is opened with [`InPlace::open()`] and read into
+    /// memory, and the hunks of the patch are applied in order.  As with
+    /// `patch`, each hunk is looked for first at the line given in its
+    /// header, adjusted by the offset at which the previous hunk was applied,
+    /// and then at increasing distances before and after that line, without
+    /// overlapping the previous hunk.  If a hunk cannot be found with all of
+    /// its context lines, it is looked for again while ignoring up to the
+    /// fuzz factor's worth of leading and trailing context lines.
+    ///
+    /// If all hunks were applied, the new contents are saved with
+    /// [`InPlaceFile::save()`].  Otherwise, the file is discarded with
+    /// [`InPlaceFile::discard()`], leaving the edited file untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::PatchFailed`] if any
+    /// hunks could not be applied, in which case the failed hunks are
+    /// available via [`InPlaceError::failed_hunks()`].  Returns an error of
+    /// kind [`InPlaceErrorKind::Read`] if reading the edited file fails, or
+    /// of kind [`InPlaceErrorKind::Write`] if writing to the temporary file
+    /// fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceFile::discard()`]: crate::InPlaceFile::discard
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::PatchFailed`]: crate::InPlaceErrorKind::PatchFailed
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::Write`]: crate::InPlaceErrorKind::Write
+    pub fn apply_patch_with(
+        &self,
+        patch: &Patch,
+        options: &PatchOptions,
+    ) -> Result<Vec<AppliedHunk>, InPlaceError> {
+        let file = self.open()?;
+        let mut content = Vec::new();
+        file.reader()
+            .read_to_end(&mut content)
+            .map_err(InPlaceError::read)?;
+        match patch.apply_to(&content, options.fuzz) {
+            Ok((out, applied)) => {
+                file.writer().write_all(&out).map_err(InPlaceError::write)?;
+                file.save()?;
+                Ok(applied)
+            }
+            Err(failed) => {
+                file.discard()?;
+                Err(InPlaceError::patch_failed(failed))
+            }
+        }
+    }
+}
+
+/// Where a hunk of a [`Patch`] was applied, as returned by
+/// [`InPlace::apply_patch()`]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AppliedHunk {
+    number: usize,
+    line: usize,
+    offset: isize,
+    fuzz: usize,
+}
+
+impl AppliedHunk {
+    /// The position of the hunk in the patch, starting from 1
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// The line of the original file at which the hunk's matched lines
+    /// start, starting from 1.  Leading context lines ignored due to fuzz are
+    /// not included.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The number of lines by which the hunk's location differs from that
+    /// given in its header
+    pub fn offset(&self) -> isize {
+        self.offset
+    }
+
+    /// The number of leading or trailing context lines that had to be ignored
+    /// in order to apply the hunk
+    pub fn fuzz(&self) -> usize {
+        self.fuzz
+    }
+}
+
+/// A hunk of a [`Patch`] that could not be applied.  See
+/// [`InPlaceError::failed_hunks()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FailedHunk {
+    number: usize,
+    line: usize,
+}
+
+impl FailedHunk {
+    /// The position of the hunk in the patch, starting from 1
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// The line of the original file at which the hunk's header says it
+    /// starts
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+/// The source error of an [`InPlaceError`] of kind
+/// [`InPlaceErrorKind::PatchFailed`]
+///
+/// [`InPlaceErrorKind::PatchFailed`]: crate::InPlaceErrorKind::PatchFailed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct HunksFailed(pub(crate) Vec<FailedHunk>);
+
+impl fmt::Display for HunksFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed hunks:")?;
+        for (i, hunk) in self.0.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(f, "{sep} #{} (line {})", hunk.number, hunk.line)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for HunksFailed {}
+
+/// An error returned when parsing a malformed unified diff
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatchParseError {
+    line: usize,
+    message: String,
+}
+
+impl PatchParseError {
+    /// The line of the diff on which the error occurred, starting from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for PatchParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for PatchParseError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Hunk {
+    /// The starting line number of the old lines, as given in the header
+    old_start: usize,
+    lines: Vec<HunkLine>,
+    /// Whether the last new line is not followed by a newline
+    new_missing_newline: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Where a hunk was found in a file
+struct Location {
+    /// The index of the first line matched
+    start: usize,
+    /// The number of leading context lines ignored
+    lead: usize,
+    /// The number of trailing context lines ignored
+    trail: usize,
+    fuzz: usize,
+}
+
+impl Hunk {
+    /// The lines of the original file covered by the hunk
+    fn old_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|ln| match ln {
+            HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+            HunkLine::Add(_) => None,
+        })
+    }
+
+    /// The index of the line at which the hunk's header says its old lines
+    /// start.  A header for a hunk without old lines gives the number of the
+    /// line after which to insert.
+    fn base(&self) -> usize {
+        if self.old_lines().next().is_none() {
+            self.old_start
+        } else {
+            self.old_start.saturating_sub(1)
+        }
+    }
+
+    fn locate(
+        &self,
+        lines: &[(&[u8], bool)],
+        min_start: usize,
+        offset: isize,
+        max_fuzz: usize,
+    ) -> Option<Location> {
+        let old = self.old_lines().collect::<Vec<_>>();
+        let lead_context = self
+            .lines
+            .iter()
+            .take_while(|ln| matches!(ln, HunkLine::Context(_)))
+            .count();
+        let trail_context = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|ln| matches!(ln, HunkLine::Context(_)))
+            .count();
+        for fuzz in 0..=max_fuzz {
+            let lead = fuzz.min(lead_context);
+            let trail = fuzz.min(trail_context).min(old.len() - lead);
+            if fuzz > 0 && lead < fuzz && trail < fuzz {
+                // Nothing more can be ignored than at the previous level
+                break;
+            }
+            let pattern = &old[lead..(old.len() - trail)];
+            let Some(max_start) = lines.len().checked_sub(pattern.len()) else {
+                continue;
+            };
+            if min_start > max_start {
+                continue;
+            }
+            let expected =
+                (signed(self.base() + lead) + offset).clamp(signed(min_start), signed(max_start));
+            let expected = usize::try_from(expected).unwrap_or(min_start);
+            let matches_at = |start: usize| {
+                pattern
+                    .iter()
+                    .zip(&lines[start..])
+                    .all(|(p, (ln, _))| p.as_bytes() == *ln)
+            };
+            for distance in 0.. {
+                let after = expected.checked_add(distance).filter(|&s| s <= max_start);
+                let before = expected.checked_sub(distance).filter(|&s| s >= min_start);
+                if after.is_none() && before.is_none() {
+                    break;
+                }
+                if let Some(start) = after.into_iter().chain(before).find(|&s| matches_at(s)) {
+                    return Some(Location {
+                        start,
+                        lead,
+                        trail,
+                        fuzz,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Append the new lines of the hunk, excluding `lead` leading and `trail`
+    /// trailing context lines, to `out`
+    fn emit<'a>(&'a self, lead: usize, trail: usize, out: &mut Vec<(&'a [u8], bool)>) {
+        let last_new = self
+            .lines
+            .iter()
+            .rposition(|ln| !matches!(ln, HunkLine::Remove(_)));
+        for (i, ln) in self
+            .lines
+            .iter()
+            .enumerate()
+            .take(self.lines.len() - trail)
+            .skip(lead)
+        {
+            if let HunkLine::Context(s) | HunkLine::Add(s) = ln {
+                let newline = !(self.new_missing_newline && Some(i) == last_new);
+                out.push((s.as_bytes(), newline));
+            }
+        }
+    }
+}
+
+/// Split `content` into lines without their terminating newlines, paired
+/// with whether each line had one
+fn split_lines(content: &[u8]) -> Vec<(&[u8], bool)> {
+    let mut lines = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        if let Some(i) = rest.iter().position(|&b| b == b'\n') {
+            lines.push((&rest[..i], true));
+            rest = &rest[(i + 1)..];
+        } else {
+            lines.push((rest, false));
+            break;
+        }
+    }
+    lines
+}
+
+fn signed(n: usize) -> isize {
+    isize::try_from(n).unwrap_or(isize::MAX)
+}
+
+/// Parse a unified diff into patches paired with the line numbers at which
+/// they start
+fn parse_patches(diff: &str) -> Result<Vec<(usize, Patch)>, PatchParseError> {
+    let lines = diff
+        .strip_suffix('\n')
+        .unwrap_or(diff)
+        .split('\n')
+        .collect::<Vec<_>>();
+    let mut patches: Vec<(usize, Patch)> = Vec::new();
+    let mut i = 0;
+    while let Some(&line) = lines.get(i) {
+        let header = line
+            .strip_prefix("--- ")
+            .zip(lines.get(i + 1).and_then(|ln| ln.strip_prefix("+++ ")));
+        if let Some((old, new)) = header {
+            if let Some((start, patch)) = patches.last() {
+                if patch.hunks.is_empty() {
+                    return Err(no_hunks(*start));
+                }
+            }
+            patches.push((
+                i + 1,
+                Patch {
+                    old_path: header_path(old),
+                    new_path: header_path(new),
+                    hunks: Vec::new(),
+                },
+            ));
+            i += 2;
+        } else if line.starts_with("@@ ") {
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            match patches.last_mut() {
+                Some((_, patch)) => patch.hunks.push(hunk),
+                None => patches.push((
+                    i + 1,
+                    Patch {
+                        old_path: None,
+                        new_path: None,
+                        hunks: vec![hunk],
+                    },
+                )),
+            }
+            i = next;
+        } else {
+            i += 1;
+        }
+    }
+    match patches.last() {
+        None => Err(no_hunks(1)),
+        Some((start, patch)) if patch.hunks.is_empty() => Err(no_hunks(*start)),
+        Some(_) => Ok(patches),
+    }
+}
+
+fn no_hunks(line: usize) -> PatchParseError {
+    PatchParseError {
+        line,
+        message: String::from("no hunks found"),
+    }
+}
+
+/// Extract the path from the remainder of a `---` or `+++` line
+fn header_path(s: &str) -> Option<String> {
+    let path = s.split('\t').next().unwrap_or(s).trim_end();
+    (path != "/dev/null").then(|| path.to_owned())
+}
+
+/// Parse the hunk whose header is at `lines[start]`, returning it and the
+/// index of the line after it
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Hunk, usize), PatchParseError> {
+    let error = |i: usize, message: &str| PatchParseError {
+        line: i + 1,
+        message: message.to_owned(),
+    };
+    let (old_start, mut old_left, mut new_left) =
+        parse_hunk_header(lines[start]).ok_or_else(|| error(start, "malformed hunk header"))?;
+    let mut hunk = Hunk {
+        old_start,
+        lines: Vec::new(),
+        new_missing_newline: false,
+    };
+    let mut i = start + 1;
+    loop {
+        let Some(&line) = lines.get(i) else {
+            if old_left > 0 || new_left > 0 {
+                return Err(error(i, "unexpected end of diff in hunk"));
+            }
+            break;
+        };
+        let mut chars = line.chars();
+        let first = chars.next();
+        let text = chars.as_str().to_owned();
+        match first {
+            Some('\\') => {
+                match hunk.lines.last() {
+                    Some(HunkLine::Context(_) | HunkLine::Add(_)) => {
+                        hunk.new_missing_newline = true;
+                    }
+                    Some(HunkLine::Remove(_)) => (),
+                    None => return Err(error(i, "\"no newline\" marker without preceding line")),
+                }
+                i += 1;
+                continue;
+            }
+            _ if old_left == 0 && new_left == 0 => break,
+            Some(' ') | None if old_left > 0 && new_left > 0 => {
+                hunk.lines.push(HunkLine::Context(text));
+                old_left -= 1;
+                new_left -= 1;
+            }
+            Some('-') if old_left > 0 => {
+                hunk.lines.push(HunkLine::Remove(text));
+                old_left -= 1;
+            }
+            Some('+') if new_left > 0 => {
+                hunk.lines.push(HunkLine::Add(text));
+                new_left -= 1;
+            }
+            Some(' ' | '-' | '+') | None => {
+                return Err(error(i, "hunk is longer than its header states"));
+            }
+            Some(_) => return Err(error(i, "malformed line in hunk")),
+        }
+        i += 1;
+    }
+    Ok((hunk, i))
+}
+
+/// Parse a hunk header of the form `@@ -START,LEN +START,LEN @@`, returning
+/// the old starting line, the old length, and the new length
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let (ranges, _) = line.strip_prefix("@@ -")?.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let (old_start, old_len) = parse_range(old)?;
+    let (_, new_len) = parse_range(new)?;
+    Some((old_start, old_len, new_len))
+}
+
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    match s.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((s.parse().ok()?, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,230 @@
+use super::{AppliedHunk, FailedHunk, HunksFailed, Patch};
+
+const ORIGINAL: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+
+fn apply(
+    diff: &str,
+    content: &str,
+    fuzz: usize,
+) -> Result<(String, Vec<AppliedHunk>), HunksFailed> {
+    let patch = Patch::parse(diff).expect("diff should parse");
+    patch
+        .apply_to(content.as_bytes(), fuzz)
+        .map(|(out, applied)| {
+            (
+                String::from_utf8(out).expect("output should be UTF-8"),
+                applied,
+            )
+        })
+}
+
+fn hunk(number: usize, line: usize, offset: isize, fuzz: usize) -> AppliedHunk {
+    AppliedHunk {
+        number,
+        line,
+        offset,
+        fuzz,
+    }
+}
+
+#[test]
+fn parse_headers() {
+    let patch = Patch::parse(concat!(
+        "diff --git a/file.txt b/file.txt\n",
+        "index 1234567..89abcde 100644\n",
+        "--- a/file.txt\t2024-01-01 00:00:00\n",
+        "+++ b/file.txt\t2024-01-02 00:00:00\n",
+        "@@ -1 +1 @@\n",
+        "-one\n",
+        "+ONE\n",
+        "@@ -5,0 +6 @@ five\n",
+        "+five and a half\n",
+    ))
+    .unwrap();
+    assert_eq!(patch.old_path(), Some("a/file.txt"));
+    assert_eq!(patch.new_path(), Some("b/file.txt"));
+    assert_eq!(patch.hunk_count(), 2);
+}
+
+#[test]
+fn parse_all() {
+    let diff = concat!(
+        "--- /dev/null\n",
+        "+++ b/new.txt\n",
+        "@@ -0,0 +1 @@\n",
+        "+new\n",
+        "--- a/old.txt\n",
+        "+++ /dev/null\n",
+        "@@ -1 +0,0 @@\n",
+        "-old\n",
+    );
+    let patches = Patch::parse_all(diff).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].old_path(), None);
+    assert_eq!(patches[0].new_path(), Some("b/new.txt"));
+    assert_eq!(patches[1].old_path(), Some("a/old.txt"));
+    assert_eq!(patches[1].new_path(), None);
+    let e = Patch::parse(diff).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "line 5: diff contains changes to more than one file"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    for (diff, message) in [
+        ("", "line 1: no hunks found"),
+        ("just some text\n", "line 1: no hunks found"),
+        (
+            "--- a\n+++ b\n--- c\n+++ d\n@@ -1 +1 @@\n-x\n+y\n",
+            "line 1: no hunks found",
+        ),
+        ("@@ -1 +1 @\n-x\n+y\n", "line 1: malformed hunk header"),
+        (
+            "@@ -1,2 +1 @@\n-x\n+y\n",
+            "line 4: unexpected end of diff in hunk",
+        ),
+        (
+            "@@ -1 +1 @@\n-x\n-y\n",
+            "line 3: hunk is longer than its header states",
+        ),
+        ("@@ -1 +1 @@\n*x\n+y\n", "line 2: malformed line in hunk"),
+    ] {
+        let e = Patch::parse(diff).unwrap_err();
+        assert_eq!(e.to_string(), message, "diff: {diff:?}");
+    }
+}
+
+#[test]
+fn apply_exact() {
+    let diff = concat!(
+        "@@ -2,3 +2,3 @@\n",
+        " two\n",
+        "-three\n",
+        "+THREE\n",
+        " four\n",
+        "@@ -8,3 +8,4 @@\n",
+        " eight\n",
+        " nine\n",
+        "+nine and a half\n",
+        " ten\n",
+    );
+    let (out, applied) = apply(diff, ORIGINAL, 2).unwrap();
+    assert_eq!(
+        out,
+        "one\ntwo\nTHREE\nfour\nfive\nsix\nseven\neight\nnine\nnine and a half\nten\n"
+    );
+    assert_eq!(applied, [hunk(1, 2, 0, 0), hunk(2, 8, 0, 0)]);
+}
+
+#[test]
+fn apply_with_offset() {
+    let diff = concat!(
+        "@@ -2,3 +2,3 @@\n",
+        " two\n",
+        "-three\n",
+        "+THREE\n",
+        " four\n",
+        "@@ -6,3 +6,2 @@\n",
+        " six\n",
+        "-seven\n",
+        " eight\n",
+    );
+    let content = format!("zero\nzero\n{ORIGINAL}");
+    let (out, applied) = apply(diff, &content, 0).unwrap();
+    assert_eq!(
+        out,
+        "zero\nzero\none\ntwo\nTHREE\nfour\nfive\nsix\neight\nnine\nten\n"
+    );
+    assert_eq!(applied, [hunk(1, 4, 2, 0), hunk(2, 8, 2, 0)]);
+}
+
+#[test]
+fn apply_with_fuzz() {
+    let diff = concat!(
+        "@@ -3,5 +3,5 @@\n",
+        " 3\n",
+        " four\n",
+        "-five\n",
+        "+FIVE\n",
+        " six\n",
+        " 7\n",
+    );
+    assert_eq!(
+        apply(diff, ORIGINAL, 0).unwrap_err(),
+        HunksFailed(vec![FailedHunk { number: 1, line: 3 }])
+    );
+    let (out, applied) = apply(diff, ORIGINAL, 1).unwrap();
+    assert_eq!(
+        out,
+        "one\ntwo\nthree\nfour\nFIVE\nsix\nseven\neight\nnine\nten\n"
+    );
+    assert_eq!(applied, [hunk(1, 4, 0, 1)]);
+}
+
+#[test]
+fn apply_failed_hunks() {
+    let diff = concat!(
+        "@@ -1,2 +1,2 @@\n",
+        "-one\n",
+        "+ONE\n",
+        " two\n",
+        "@@ -4,2 +4,2 @@\n",
+        "-vier\n",
+        "+FOUR\n",
+        " five\n",
+        "@@ -9 +9 @@\n",
+        "-neun\n",
+        "+NINE\n",
+    );
+    let e = apply(diff, ORIGINAL, 2).unwrap_err();
+    assert_eq!(
+        e.0,
+        [
+            FailedHunk { number: 2, line: 4 },
+            FailedHunk { number: 3, line: 9 }
+        ]
+    );
+    assert_eq!(e.to_string(), "failed hunks: #2 (line 4), #3 (line 9)");
+}
+
+#[test]
+fn apply_no_newline_at_end() {
+    let diff = concat!(
+        "@@ -9,2 +9,2 @@\n",
+        " nine\n",
+        "-ten\n",
+        "+TEN\n",
+        "\\ No newline at end of file\n",
+    );
+    let (out, _) = apply(diff, ORIGINAL, 0).unwrap();
+    assert_eq!(
+        out,
+        "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nTEN"
+    );
+    let diff = concat!(
+        "@@ -1 +1,2 @@\n",
+        "-a\n",
+        "\\ No newline at end of file\n",
+        "+a\n",
+        "+b\n",
+    );
+    let (out, _) = apply(diff, "a", 0).unwrap();
+    assert_eq!(out, "a\nb\n");
+}
+
+#[test]
+fn apply_to_empty() {
+    let diff = "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+hello\n+world\n";
+    let (out, applied) = apply(diff, "", 0).unwrap();
+    assert_eq!(out, "hello\nworld\n");
+    assert_eq!(applied, [hunk(1, 1, 0, 0)]);
+}
+
+#[test]
+fn apply_crlf() {
+    let diff = "@@ -1,2 +1,2 @@\n-a\r\n+A\r\n b\r\n";
+    let (out, _) = apply(diff, "a\r\nb\r\n", 0).unwrap();
+    assert_eq!(out, "A\r\nb\r\n");
+}
@@ -0,0 +1,171 @@
+use crate::{InPlace, InPlaceError};
+use regex::bytes::Regex;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+/// Options for [`InPlace::replace_all_with()`]
+#[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ReplaceOptions {
+    line_wise: bool,
+    limit: Option<usize>,
+}
+
+impl ReplaceOptions {
+    /// Create a new `ReplaceOptions` with the default settings: the whole
+    /// file is searched at once, and all matches are replaced
+    pub fn new() -> ReplaceOptions {
+        ReplaceOptions::default()
+    }
+
+    /// If `flag` is true, the pattern is applied to each line of the edited
+    /// file separately, excluding the line terminator (LF or CR LF), and the
+    /// file is streamed rather than read into memory.  If `flag` is false
+    /// (the default), the pattern is applied to the entire contents of the
+    /// file at once, and so it can match across lines.
+    pub fn line_wise(&mut self, flag: bool) -> &mut Self {
+        self.line_wise = flag;
+        self
+    }
+
+    /// Replace at most `max` matches in total, starting from the beginning of
+    /// the file.  By default, all matches are replaced.
+    pub fn limit(&mut self, max: usize) -> &mut Self {
+        self.limit = Some(max);
+        self
+    }
+}
+
+impl InPlace {
+    /// Replace all matches of `pattern` in the edited file with
+    /// `replacement`, searching the whole file at once, and return the number
+    /// of replacements made.  This is equivalent to calling
+    /// [`InPlace::replace_all_with()`] with the default [`ReplaceOptions`].
+    ///
+    /// # Errors
+    ///
+    /// See [`InPlace::replace_all_with()`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    pub fn replace_all(&self, pattern: &Regex, replacement: &str) -> Result<usize, InPlaceError> {
+        self.replace_all_with(pattern, replacement, &ReplaceOptions::new())
+    }
+
+    /// Replace matches of `pattern` in the edited file with `replacement` as
+    /// configured by `options`, and return the number of replacements made.
+    ///
+    /// The edited file is opened with [`InPlace::open()`].  `replacement` may
+    /// refer to capture groups of `pattern` using the syntax of
+    /// [`regex::bytes::Captures::expand()`], e.g., `$1` or `${name}`.  As the
+    /// pattern is a [`regex::bytes::Regex`], files that are not valid UTF-8
+    /// can be edited.
+    ///
+    /// If the replacements changed the file's contents, the file is saved
+    /// with [`InPlaceFile::save()`].  Otherwise (including when every match
+    /// was replaced with identical text), the file is discarded with
+    /// [`InPlaceFile::discard()`], so that unchanged files are left
+    /// untouched, and no backup is made.  In either case, the number of
+    /// matches replaced is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Read`] if reading the
+    /// edited file fails, or of kind [`InPlaceErrorKind::Write`] if writing
+    /// to the temporary file fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceFile::discard()`]: crate::InPlaceFile::discard
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::Write`]: crate::InPlaceErrorKind::Write
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    pub fn replace_all_with(
+        &self,
+        pattern: &Regex,
+        replacement: &str,
+        options: &ReplaceOptions,
+    ) -> Result<usize, InPlaceError> {
+        let file = self.open()?;
+        let replacement = replacement.as_bytes();
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let mut count = 0;
+        let changed;
+        if options.line_wise {
+            let mut any_changed = false;
+            let mut reader = BufReader::new(file.reader());
+            let mut writer = BufWriter::new(file.writer());
+            let mut line = Vec::new();
+            let mut out = Vec::new();
+            loop {
+                line.clear();
+                if reader
+                    .read_until(b'\n', &mut line)
+                    .map_err(InPlaceError::read)?
+                    == 0
+                {
+                    break;
+                }
+                let (content, terminator) = line.split_at(content_len(&line));
+                out.clear();
+                count += replace_into(pattern, content, replacement, limit - count, &mut out);
+                any_changed |= out != content;
+                out.extend_from_slice(terminator);
+                writer.write_all(&out).map_err(InPlaceError::write)?;
+            }
+            writer
+                .into_inner()
+                .map_err(|e| InPlaceError::write(e.into_error()))?;
+            changed = any_changed;
+        } else {
+            let mut content = Vec::new();
+            file.reader()
+                .read_to_end(&mut content)
+                .map_err(InPlaceError::read)?;
+            let mut out = Vec::with_capacity(content.len());
+            count = replace_into(pattern, &content, replacement, limit, &mut out);
+            changed = out != content;
+            if changed {
+                file.writer().write_all(&out).map_err(InPlaceError::write)?;
+            }
+        }
+        if changed {
+            file.save()?;
+        } else {
+            file.discard()?;
+        }
+        Ok(count)
+    }
+}
+
+/// Append `haystack` to `out` with up to `limit` matches of `pattern`
+/// replaced by the expansion of `replacement`, and return the number of
+/// replacements made
+fn replace_into(
+    pattern: &Regex,
+    haystack: &[u8],
+    replacement: &[u8],
+    limit: usize,
+    out: &mut Vec<u8>,
+) -> usize {
+    let mut last = 0;
+    let mut count = 0;
+    for caps in pattern.captures_iter(haystack).take(limit) {
+        if let Some(m) = caps.get(0) {
+            out.extend_from_slice(&haystack[last..m.start()]);
+            caps.expand(replacement, out);
+            last = m.end();
+            count += 1;
+        }
+    }
+    out.extend_from_slice(&haystack[last..]);
+    count
+}
+
+/// The length of `line` without its line terminator (LF or CR LF), if any
+fn content_len(line: &[u8]) -> usize {
+    match line {
+        [rest @ .., b'\r', b'\n'] | [rest @ .., b'\n'] => rest.len(),
+        _ => line.len(),
+    }
+}
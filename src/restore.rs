@@ -0,0 +1,208 @@
+#[cfg(feature = "store")]
+use crate::Backup;
+use crate::{InPlace, InPlaceError, absolutize};
+use std::fs::{File, Metadata, remove_file, rename, symlink_metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+impl InPlace {
+    /// Undo a previous edit of the edited path by moving its backup back into
+    /// place, and return the path of the backup that was restored.
+    ///
+    /// The backup path is computed using the same rules as
+    /// [`InPlace::open()`].  The edited path is resolved as though `create`
+    /// were set, so it need not exist.  As there is no numbered backup
+    /// specifier, numbered backups (such as those made by the `in-place`
+    /// command's `--numbered` option) are not found automatically; to
+    /// restore one, set the backup specifier to a [`Backup::FileName`] with
+    /// the backup's filename.
+    ///
+    /// Before anything is moved, a best-effort check is made that the edited
+    /// file has not been modified since the backup was made, and restoring
+    /// fails with an error of kind [`InPlaceErrorKind::Modified`] if it has:
+    /// on Unix, the edited file is considered modified if its modification
+    /// time is later than the backup's status change time (which is updated
+    /// when the backup is created by [`InPlaceFile::save()`]).  This misses
+    /// modifications made within the filesystem's timestamp granularity of
+    /// the save or after the backup's metadata was changed, and no check is
+    /// performed on other platforms.  For an exact check, use
+    /// [`InPlace::restore_saved()`] instead.  No check is performed if the
+    /// edited file does not exist.
+    ///
+    /// The backup is moved over the edited path with an atomic rename.  If
+    /// the backup is on a different filesystem from the edited path, its
+    /// contents & permissions are instead copied to a temporary file that is
+    /// then renamed over the edited path, after which the backup is deleted.
+    /// If `InPlace::compress_backup()` was set, the backup is always
+    /// decompressed to a temporary file that is then renamed over the edited
+    /// path, after which the backup is deleted.
+    ///
+    /// For `Backup::Store` (requires the `store` feature), the most recently
+    /// stored version of the edited path is restored as though by
+    /// `InPlace::restore_version()`, and the path to the stored copy is
+    /// returned.  In this case, the edited file is instead considered to have
+    /// been modified if its modification time is later than the time at which
+    /// the version was stored, and this check is performed on all platforms.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations & checks that this method can fail on.
+    ///
+    /// [`Backup::FileName`]: crate::Backup::FileName
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Modified`]: crate::InPlaceErrorKind::Modified
+    pub fn restore(&self) -> Result<PathBuf, InPlaceError> {
+        self.restore_impl(None)
+    }
+
+    /// Undo a previous edit of the edited path as with [`InPlace::restore()`],
+    /// but check that the edited file has not been modified by comparing it
+    /// against `saved`, the identity of the file as it was saved (as returned
+    /// by [`SavedFile::identity()`]).  If the edited file's size,
+    /// modification time, or (on Unix) device & inode numbers differ from
+    /// those in `saved`, restoring fails with an error of kind
+    /// [`InPlaceErrorKind::Modified`].  This check is performed on all
+    /// platforms and for all backup specifiers.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations & checks that this method can fail on.
+    ///
+    /// [`SavedFile::identity()`]: crate::SavedFile::identity
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Modified`]: crate::InPlaceErrorKind::Modified
+    pub fn restore_saved(&self, saved: &FileIdentity) -> Result<PathBuf, InPlaceError> {
+        self.restore_impl(Some(saved))
+    }
+
+    fn restore_impl(&self, saved: Option<&FileIdentity>) -> Result<PathBuf, InPlaceError> {
+        let mut inp = self.clone();
+        inp.create = true;
+        let (path, _) = inp.resolve()?;
+        let backup_path = match self.backup.as_ref() {
+            None => return Err(InPlaceError::no_backup()),
+            #[cfg(feature = "store")]
+            Some(Backup::Store(_)) => return self.restore_latest(&path, saved),
+            Some(bkp) => bkp.apply(&path)?.ok_or_else(InPlaceError::no_backup)?,
+        };
+        let backup_path = self.compressed_path(absolutize(&backup_path)?);
+        let backup_md = match symlink_metadata(&backup_path) {
+            Ok(md) => md,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(InPlaceError::no_backup());
+            }
+            Err(e) => return Err(InPlaceError::restore(e)),
+        };
+        match symlink_metadata(&path) {
+            Ok(md)
+                if saved.map_or_else(
+                    || modified_since_backup(&md, &backup_md),
+                    |ident| ident.differs_from(&md),
+                ) =>
+            {
+                return Err(InPlaceError::modified());
+            }
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(InPlaceError::get_metadata(e)),
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some(c) = self.compression {
+            let tmp = self.temp_name.mktemp(&path, None)?;
+            c.decompress_into_place(&backup_path, &path, tmp)
+                .map_err(InPlaceError::restore)?;
+            return Ok(backup_path);
+        }
+        match rename(&backup_path, &path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                self.copy_into_place(&backup_path, &path, backup_md)?;
+            }
+            Err(e) => return Err(InPlaceError::restore(e)),
+        }
+        Ok(backup_path)
+    }
+
+    /// Replace `path` with a copy of `backup_path` via a temporary file, and
+    /// then delete `backup_path`
+    fn copy_into_place(
+        &self,
+        backup_path: &Path,
+        path: &Path,
+        backup_md: Metadata,
+    ) -> Result<(), InPlaceError> {
+        let mut src = File::open(backup_path).map_err(InPlaceError::restore)?;
+        let mut tmp = self.temp_name.mktemp(path, Some(backup_md.permissions()))?;
+        io::copy(&mut src, tmp.as_file_mut()).map_err(InPlaceError::restore)?;
+        drop(src);
+        tmp.persist(path)
+            .map_err(|e| InPlaceError::restore(e.error))?;
+        remove_file(backup_path).map_err(InPlaceError::restore)
+    }
+}
+
+/// Returns true if the file with metadata `current` was modified after the
+/// file with metadata `backup` was created
+#[cfg(unix)]
+fn modified_since_backup(current: &Metadata, backup: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (current.mtime(), current.mtime_nsec()) > (backup.ctime(), backup.ctime_nsec())
+}
+
+#[cfg(not(unix))]
+fn modified_since_backup(_current: &Metadata, _backup: &Metadata) -> bool {
+    false
+}
+
+/// Identifying details of a specific version of a file: its size,
+/// modification time, and (on Unix) device & inode numbers.
+///
+/// A `FileIdentity` for a saved file can be obtained via
+/// [`SavedFile::identity()`] and passed to [`InPlace::restore_saved()`] in
+/// order to check that the file has not been modified since it was saved.
+///
+/// [`SavedFile::identity()`]: crate::SavedFile::identity
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FileIdentity {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+}
+
+impl FileIdentity {
+    /// Fetch the identity of the file at `path`.  If `path` is a symlink, the
+    /// identity of the symlink itself is returned.
+    ///
+    /// # Errors
+    ///
+    /// Fails if fetching the metadata for `path` fails.
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<FileIdentity> {
+        symlink_metadata(path).map(|md| FileIdentity::from_metadata(&md))
+    }
+
+    fn from_metadata(md: &Metadata) -> FileIdentity {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+        FileIdentity {
+            len: md.len(),
+            modified: md.modified().ok(),
+            #[cfg(unix)]
+            dev: md.dev(),
+            #[cfg(unix)]
+            ino: md.ino(),
+        }
+    }
+
+    /// Returns true if the file with metadata `current` is not the version
+    /// of the file identified by `self`
+    pub(crate) fn differs_from(&self, current: &Metadata) -> bool {
+        *self != FileIdentity::from_metadata(current)
+    }
+}
@@ -0,0 +1,166 @@
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::{Compression, TempName};
+use crate::{FileIdentity, InPlaceError};
+use std::fs::{remove_file, rename};
+use std::io;
+use std::path::{Path, PathBuf};
+use tempfile::TempPath;
+
+/// A handle to an edited file that has been saved, which can be used to undo
+/// the edit.
+///
+/// A `SavedFile` instance can be obtained via
+/// [`InPlaceFile::save_revertible()`] or
+/// [`AtomicWriteFile::commit_revertible()`].  Calling [`SavedFile::revert()`]
+/// restores the edited file's original contents, while
+/// [`SavedFile::finalize()`] keeps the edit and deletes the hidden backup, if
+/// any.
+///
+/// Dropping a `SavedFile` without calling `revert()` has the same effect as
+/// calling `finalize()`, except that any errors are ignored.
+///
+/// [`InPlaceFile::save_revertible()`]: crate::InPlaceFile::save_revertible
+/// [`AtomicWriteFile::commit_revertible()`]: crate::AtomicWriteFile::commit_revertible
+#[derive(Debug)]
+pub struct SavedFile {
+    path: PathBuf,
+    original: Original,
+    identity: Option<FileIdentity>,
+}
+
+/// Where the original contents of a saved file can be found
+#[derive(Debug)]
+pub(crate) enum Original {
+    /// The edited file did not exist before it was saved.  (For saves that
+    /// are not revertible, this is also used when no backup was made.)
+    Missing,
+
+    /// The edited file was moved to the configured backup path
+    Backup(PathBuf),
+
+    /// The edited file was compressed to the configured backup path.  The
+    /// `TempName` is used for the temporary file that the backup is
+    /// decompressed to on reverting.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    Compressed(PathBuf, Compression, TempName),
+
+    /// The edited file was copied to the given path in a backup store
+    #[cfg(feature = "store")]
+    Stored(PathBuf),
+
+    /// The edited file was moved to a hidden backup that is deleted when the
+    /// `SavedFile` is finalized
+    Hidden(TempPath),
+
+    /// The edited file was not modified, as `dry_run` was set
+    Unmodified,
+}
+
+impl Original {
+    /// The path at which the original file is stored, if any
+    pub(crate) fn location(&self) -> Option<&Path> {
+        match self {
+            Original::Backup(bp) => Some(bp),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            Original::Compressed(bp, ..) => Some(bp),
+            #[cfg(feature = "store")]
+            Original::Stored(blob) => Some(blob),
+            Original::Hidden(tp) => Some(tp),
+            Original::Missing | Original::Unmodified => None,
+        }
+    }
+}
+
+impl SavedFile {
+    pub(crate) fn new(path: PathBuf, original: Original) -> SavedFile {
+        let identity = FileIdentity::of(&path).ok();
+        SavedFile {
+            path,
+            original,
+            identity,
+        }
+    }
+
+    /// The path to the edited file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path, if any, at which the original edited file is currently
+    /// stored.  This is either the configured backup path, the path to the
+    /// hidden backup, or the path to the copy in the backup store.
+    pub fn backup_path(&self) -> Option<&Path> {
+        self.original.location()
+    }
+
+    /// The identity of the edited file as it was saved, for passing to
+    /// [`InPlace::restore_saved()`] in order to restore the backup later.
+    /// This is `None` if fetching the identity failed.
+    ///
+    /// [`InPlace::restore_saved()`]: crate::InPlace::restore_saved
+    pub fn identity(&self) -> Option<FileIdentity> {
+        self.identity
+    }
+
+    /// Undo the edit by moving the original edited file back into place.  If
+    /// the backup was compressed, it is decompressed into place and then
+    /// deleted.  If the backup was made to a backup store, the stored copy is
+    /// copied into place and left in the store.
+    ///
+    /// If the edited file did not exist before it was saved, it is deleted
+    /// instead.  If the file was saved with `dry_run` set, this does nothing.
+    ///
+    /// If moving the backup fails, the backup (hidden or not) is left in
+    /// place.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Revert`] if moving the
+    /// backup or deleting the edited file fails.
+    ///
+    /// [`InPlaceErrorKind::Revert`]: crate::InPlaceErrorKind::Revert
+    pub fn revert(self) -> Result<(), InPlaceError> {
+        match self.original {
+            Original::Missing => match remove_file(&self.path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(InPlaceError::revert(e)),
+            },
+            Original::Backup(bp) => rename(bp, &self.path).map_err(InPlaceError::revert),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            Original::Compressed(bp, c, temp_name) => {
+                let tmp = temp_name.mktemp(&self.path, None)?;
+                c.decompress_into_place(&bp, &self.path, tmp)
+                    .map_err(InPlaceError::revert)
+            }
+            #[cfg(feature = "store")]
+            Original::Stored(blob) => {
+                crate::store::copy_blob_into_place(&blob, &self.path).map_err(InPlaceError::revert)
+            }
+            Original::Hidden(tp) => {
+                let r = rename(&tp, &self.path);
+                // Whether or not the rename succeeded, the hidden backup must
+                // not be deleted by the `TempPath` destructor
+                let _ = tp.keep();
+                r.map_err(InPlaceError::revert)
+            }
+            Original::Unmodified => Ok(()),
+        }
+    }
+
+    /// Keep the edit and delete the hidden backup, if any.  A configured
+    /// backup is left in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Rmtemp`] if deleting the
+    /// hidden backup fails.
+    ///
+    /// [`InPlaceErrorKind::Rmtemp`]: crate::InPlaceErrorKind::Rmtemp
+    pub fn finalize(self) -> Result<(), InPlaceError> {
+        match self.original {
+            Original::Hidden(tp) => tp.close().map_err(InPlaceError::rmtemp),
+            _ => Ok(()),
+        }
+    }
+}
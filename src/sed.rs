@@ -0,0 +1,693 @@
+use crate::{InPlace, InPlaceError, InPlaceFile};
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use std::error;
+use std::fmt;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// A parsed script in a subset of the `sed` language, for editing files with
+/// [`InPlace::sed()`] or [`SedScript::run()`].
+///
+/// The following commands are supported:
+///
+/// - `s/REGEX/REPLACEMENT/FLAGS` — Replace the first match of `REGEX` in the
+///   pattern space with `REPLACEMENT`.  Any character other than backslash or
+///   newline can be used in place of `/`.  In `REPLACEMENT`, `&` refers to
+///   the whole match, `\1` through `\9` refer to capture groups, and `\n`
+///   and `\t` are a newline and a tab.  `FLAGS` may be any combination of `g`
+///   (replace all matches), a number `N` (replace only the `N`th match, or
+///   the `N`th and all following matches if `g` is also given), `p` (print
+///   the pattern space if a replacement was made), and `i` or `I` (match
+///   case-insensitively).
+///
+/// - `d` — Delete the pattern space and start the next cycle
+///
+/// - `p` — Print the pattern space
+///
+/// - `a TEXT`, `i TEXT`, `c TEXT` — Append `TEXT` after the current line,
+///   insert `TEXT` before it, or delete the pattern space and print `TEXT`
+///   in its place (for a range, once at the end of the range).  `TEXT` may
+///   also be given on the following line after `a\`, `i\`, or `c\`, and it
+///   can be continued across lines by ending each line but the last with a
+///   backslash.
+///
+/// - `h`, `H`, `g`, `G`, `x` — Copy the pattern space to the hold space,
+///   append it to the hold space, copy the hold space to the pattern space,
+///   append the hold space to the pattern space, or exchange the two
+///
+/// - `=` — Print the current line number
+///
+/// Each command may be preceded by zero, one, or two addresses separated by a
+/// comma, optionally followed by `!` to negate them.  An address is a line
+/// number, `$` for the last line, or `/REGEX/` (or `\cREGEXc` for any
+/// character `c`), optionally followed by `I` to match case-insensitively.
+/// A two-address range starts at the first line matching the first address
+/// and continues through the next line matching the second address, or
+/// through the line with the given number.
+///
+/// Commands are separated by newlines or semicolons, and `#` starts a
+/// comment.  As with GNU `sed`, a script whose first line is `#n` does not
+/// print the pattern space at the end of each cycle, as though by `sed -n`.
+///
+/// Regular expressions use the syntax of the [`regex`] crate, which is
+/// similar to POSIX extended regular expressions (as used by `sed -E`), and
+/// they are matched against bytes, so files that are not valid UTF-8 can be
+/// edited.  Block commands (`{ ... }`) and commands that read additional
+/// input or quit early are not supported.
+#[cfg_attr(docsrs, doc(cfg(feature = "sed")))]
+#[derive(Clone, Debug)]
+pub struct SedScript {
+    commands: Vec<Command>,
+    quiet: bool,
+}
+
+impl SedScript {
+    /// Parse a `sed` script
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script uses unsupported syntax or is malformed,
+    /// including if it contains an invalid regular expression.
+    pub fn parse(script: &str) -> Result<SedScript, SedError> {
+        let quiet = script == "#n" || script.starts_with("#n\n");
+        let commands = Parser::new(script).parse()?;
+        Ok(SedScript { commands, quiet })
+    }
+
+    /// If `flag` is true, the pattern space is not printed at the end of each
+    /// cycle, as with `sed -n`, and only explicitly printed output is
+    /// written.  The default is false unless the script begins with `#n`.
+    pub fn quiet(&mut self, flag: bool) -> &mut Self {
+        self.quiet = flag;
+        self
+    }
+
+    /// Run the script over the contents of `file`, reading from
+    /// [`InPlaceFile::reader()`] and writing the output to
+    /// [`InPlaceFile::writer()`].  The file is not saved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Read`] if reading fails,
+    /// or of kind [`InPlaceErrorKind::Write`] if writing fails.
+    ///
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::Write`]: crate::InPlaceErrorKind::Write
+    pub fn run(&self, file: &InPlaceFile) -> Result<(), InPlaceError> {
+        let mut writer = BufWriter::new(file.writer());
+        self.execute(BufReader::new(file.reader()), &mut writer)?;
+        writer
+            .into_inner()
+            .map(drop)
+            .map_err(|e| InPlaceError::write(e.into_error()))
+    }
+
+    fn execute<R: BufRead, W: Write>(&self, mut reader: R, writer: W) -> Result<(), InPlaceError> {
+        let mut out = LineWriter {
+            inner: writer,
+            missing_newline: false,
+        };
+        let mut active = vec![false; self.commands.len()];
+        let mut hold = Vec::new();
+        let mut lineno = 0;
+        let mut next = read_line(&mut reader)?;
+        while let Some((mut pattern, newline)) = next.take() {
+            next = read_line(&mut reader)?;
+            lineno += 1;
+            let line = Line {
+                number: lineno,
+                last: next.is_none(),
+            };
+            let mut autoprint = !self.quiet;
+            let mut appended = Vec::new();
+            for (cmd, active) in self.commands.iter().zip(&mut active) {
+                if cmd.matches(&line, &pattern, active) == cmd.negated {
+                    continue;
+                }
+                match &cmd.kind {
+                    CommandKind::Substitute(sub) => {
+                        if sub.apply(&mut pattern) && sub.print {
+                            out.line(&pattern, newline)?;
+                        }
+                    }
+                    CommandKind::Delete => {
+                        autoprint = false;
+                        break;
+                    }
+                    CommandKind::Print => out.line(&pattern, newline)?,
+                    CommandKind::Append(text) => appended.push(text),
+                    CommandKind::Insert(text) => out.line(text, true)?,
+                    CommandKind::Change(text) => {
+                        let mid_range = cmd.addr2.is_some() && !cmd.negated && *active;
+                        if !mid_range {
+                            out.line(text, true)?;
+                        }
+                        autoprint = false;
+                        break;
+                    }
+                    CommandKind::Hold => hold.clone_from(&pattern),
+                    CommandKind::HoldAppend => {
+                        hold.push(b'\n');
+                        hold.extend_from_slice(&pattern);
+                    }
+                    CommandKind::Get => pattern.clone_from(&hold),
+                    CommandKind::GetAppend => {
+                        pattern.push(b'\n');
+                        pattern.extend_from_slice(&hold);
+                    }
+                    CommandKind::Exchange => std::mem::swap(&mut pattern, &mut hold),
+                    CommandKind::LineNumber => out.line(lineno.to_string().as_bytes(), true)?,
+                }
+            }
+            if autoprint {
+                out.line(&pattern, newline)?;
+            }
+            for text in appended {
+                out.line(text, true)?;
+            }
+        }
+        out.inner.flush().map_err(InPlaceError::write)
+    }
+}
+
+impl InPlace {
+    /// Open the edited file, run `script` over its contents, and save the
+    /// output as the new contents of the file.  The file is saved with
+    /// [`InPlaceFile::save()`] and so is backed up as configured.  If the
+    /// script fails, the file is discarded.
+    ///
+    /// # Errors
+    ///
+    /// See [`SedScript::run()`] and the documentation for the variants of
+    /// [`InPlaceErrorKind`] for the operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    #[cfg_attr(docsrs, doc(cfg(feature = "sed")))]
+    pub fn sed(&self, script: &SedScript) -> Result<(), InPlaceError> {
+        let file = self.open()?;
+        script.run(&file)?;
+        file.save()
+    }
+}
+
+/// An error returned when parsing an invalid or unsupported `sed` script
+#[cfg_attr(docsrs, doc(cfg(feature = "sed")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SedError {
+    line: usize,
+    message: String,
+}
+
+impl SedError {
+    /// The line of the script on which the error occurred, starting from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for SedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for SedError {}
+
+#[derive(Clone, Debug)]
+struct Command {
+    addr1: Option<Address>,
+    addr2: Option<Address>,
+    negated: bool,
+    kind: CommandKind,
+}
+
+impl Command {
+    /// Test whether the command's addresses select the current line,
+    /// updating the range state in `active`
+    fn matches(&self, line: &Line, pattern: &[u8], active: &mut bool) -> bool {
+        let Some(addr1) = self.addr1.as_ref() else {
+            return true;
+        };
+        let Some(addr2) = self.addr2.as_ref() else {
+            return addr1.matches(line, pattern);
+        };
+        if *active {
+            if match addr2 {
+                Address::Line(n) => line.number >= *n,
+                _ => addr2.matches(line, pattern),
+            } {
+                *active = false;
+            }
+            true
+        } else if addr1.matches(line, pattern) {
+            // A range whose end is a line number that has already been
+            // reached (or `$` on the last line) only covers one line.
+            *active = match addr2 {
+                Address::Line(n) => *n > line.number,
+                Address::Last => !line.last,
+                Address::Regex(_) => true,
+            };
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The position of the current input line
+struct Line {
+    number: usize,
+    last: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Address {
+    Line(usize),
+    Last,
+    Regex(Regex),
+}
+
+impl Address {
+    fn matches(&self, line: &Line, pattern: &[u8]) -> bool {
+        match self {
+            Address::Line(n) => line.number == *n,
+            Address::Last => line.last,
+            Address::Regex(re) => re.is_match(pattern),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum CommandKind {
+    Substitute(Substitution),
+    Delete,
+    Print,
+    Append(Vec<u8>),
+    Insert(Vec<u8>),
+    Change(Vec<u8>),
+    Hold,
+    HoldAppend,
+    Get,
+    GetAppend,
+    Exchange,
+    LineNumber,
+}
+
+#[derive(Clone, Debug)]
+struct Substitution {
+    regex: Regex,
+    replacement: Vec<ReplacementPart>,
+    global: bool,
+    occurrence: usize,
+    print: bool,
+}
+
+impl Substitution {
+    /// Perform the substitution on `pattern`, returning whether a
+    /// replacement was made
+    fn apply(&self, pattern: &mut Vec<u8>) -> bool {
+        let mut out = Vec::new();
+        let mut last = 0;
+        let mut replaced = false;
+        for (i, caps) in self.regex.captures_iter(pattern).enumerate() {
+            if i + 1 < self.occurrence {
+                continue;
+            }
+            if let Some(m) = caps.get(0) {
+                out.extend_from_slice(&pattern[last..m.start()]);
+                self.expand(&caps, &mut out);
+                last = m.end();
+                replaced = true;
+            }
+            if !self.global {
+                break;
+            }
+        }
+        if replaced {
+            out.extend_from_slice(&pattern[last..]);
+            *pattern = out;
+        }
+        replaced
+    }
+
+    fn expand(&self, caps: &Captures<'_>, out: &mut Vec<u8>) {
+        for part in &self.replacement {
+            match part {
+                ReplacementPart::Literal(s) => out.extend_from_slice(s),
+                ReplacementPart::Group(i) => {
+                    if let Some(m) = caps.get(*i) {
+                        out.extend_from_slice(m.as_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ReplacementPart {
+    Literal(Vec<u8>),
+    Group(usize),
+}
+
+/// A writer that tracks whether the last line written lacked a trailing
+/// newline, so that one can be inserted if more output follows
+struct LineWriter<W> {
+    inner: W,
+    missing_newline: bool,
+}
+
+impl<W: Write> LineWriter<W> {
+    fn line(&mut self, text: &[u8], newline: bool) -> Result<(), InPlaceError> {
+        let mut buf = Vec::with_capacity(text.len() + 2);
+        if self.missing_newline {
+            buf.push(b'\n');
+        }
+        buf.extend_from_slice(text);
+        if newline {
+            buf.push(b'\n');
+        }
+        self.missing_newline = !newline;
+        self.inner.write_all(&buf).map_err(InPlaceError::write)
+    }
+}
+
+/// Read a line without its terminating newline, and return it along with
+/// whether it had one
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<(Vec<u8>, bool)>, InPlaceError> {
+    let mut line = Vec::new();
+    if reader
+        .read_until(b'\n', &mut line)
+        .map_err(InPlaceError::read)?
+        == 0
+    {
+        return Ok(None);
+    }
+    let newline = line.last() == Some(&b'\n');
+    if newline {
+        line.pop();
+    }
+    Ok(Some((line, newline)))
+}
+
+struct Parser<'a> {
+    script: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(script: &'a str) -> Parser<'a> {
+        Parser { script, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.script[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> SedError {
+        SedError {
+            line: self.script[..self.pos].matches('\n').count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.next();
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Command>, SedError> {
+        let mut commands = Vec::new();
+        loop {
+            while self.peek().is_some_and(|c| c.is_whitespace() || c == ';') {
+                self.next();
+            }
+            match self.peek() {
+                None => return Ok(commands),
+                Some('#') => {
+                    self.skip_comment();
+                    continue;
+                }
+                Some(_) => (),
+            }
+            let addr1 = self.parse_address()?;
+            let addr2 = if addr1.is_some() && self.peek() == Some(',') {
+                self.next();
+                Some(
+                    self.parse_address()?
+                        .ok_or_else(|| self.error("expected address after `,`"))?,
+                )
+            } else {
+                None
+            };
+            self.skip_blanks();
+            let negated = self.peek() == Some('!');
+            if negated {
+                self.next();
+                self.skip_blanks();
+            }
+            let kind = match self.next() {
+                None => return Err(self.error("missing command")),
+                Some('s') => CommandKind::Substitute(self.parse_substitution()?),
+                Some('d') => CommandKind::Delete,
+                Some('p') => CommandKind::Print,
+                Some('a') => CommandKind::Append(self.parse_text('a')?),
+                Some('i') => CommandKind::Insert(self.parse_text('i')?),
+                Some('c') => CommandKind::Change(self.parse_text('c')?),
+                Some('h') => CommandKind::Hold,
+                Some('H') => CommandKind::HoldAppend,
+                Some('g') => CommandKind::Get,
+                Some('G') => CommandKind::GetAppend,
+                Some('x') => CommandKind::Exchange,
+                Some('=') => CommandKind::LineNumber,
+                Some(c) => return Err(self.error(format!("unknown or unsupported command: `{c}`"))),
+            };
+            if !matches!(
+                kind,
+                CommandKind::Append(_) | CommandKind::Insert(_) | CommandKind::Change(_)
+            ) {
+                self.skip_blanks();
+                match self.peek() {
+                    None | Some(';' | '\n' | '#') => (),
+                    Some(c) => {
+                        return Err(self.error(format!("extra characters after command: `{c}`")));
+                    }
+                }
+            }
+            commands.push(Command {
+                addr1,
+                addr2,
+                negated,
+                kind,
+            });
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while self.next().is_some_and(|c| c != '\n') {}
+    }
+
+    fn parse_address(&mut self) -> Result<Option<Address>, SedError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.next();
+                }
+                match self.script[start..self.pos].parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(Some(Address::Line(n))),
+                    _ => Err(self.error("invalid line number")),
+                }
+            }
+            Some('$') => {
+                self.next();
+                Ok(Some(Address::Last))
+            }
+            Some('/') => {
+                self.next();
+                self.parse_address_regex('/').map(Some)
+            }
+            Some('\\') => {
+                self.next();
+                match self.next() {
+                    Some(delim) if delim != '\n' && delim != '\\' => {
+                        self.parse_address_regex(delim).map(Some)
+                    }
+                    _ => Err(self.error("invalid regular expression delimiter")),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_address_regex(&mut self, delim: char) -> Result<Address, SedError> {
+        let pattern = self.read_delimited(delim, true)?;
+        let case_insensitive = self.peek() == Some('I');
+        if case_insensitive {
+            self.next();
+        }
+        self.build_regex(&pattern, case_insensitive)
+            .map(Address::Regex)
+    }
+
+    fn parse_substitution(&mut self) -> Result<Substitution, SedError> {
+        let delim = match self.next() {
+            Some(c) if c != '\n' && c != '\\' => c,
+            _ => return Err(self.error("invalid delimiter for `s` command")),
+        };
+        let pattern = self.read_delimited(delim, true)?;
+        let replacement = parse_replacement(&self.read_delimited(delim, false)?);
+        let mut global = false;
+        let mut print = false;
+        let mut case_insensitive = false;
+        let mut occurrence = None;
+        loop {
+            match self.peek() {
+                Some('g') => global = true,
+                Some('p') => print = true,
+                Some('i' | 'I') => case_insensitive = true,
+                Some(c) if c.is_ascii_digit() => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.next();
+                    }
+                    match self.script[start..self.pos].parse::<usize>() {
+                        Ok(n) if n > 0 && occurrence.is_none() => occurrence = Some(n),
+                        _ => return Err(self.error("invalid occurrence number for `s` command")),
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.next();
+        }
+        Ok(Substitution {
+            regex: self.build_regex(&pattern, case_insensitive)?,
+            replacement,
+            global,
+            occurrence: occurrence.unwrap_or(1),
+            print,
+        })
+    }
+
+    /// Read up to the next unescaped occurrence of `delim` and return the
+    /// text before it.  An escaped delimiter is replaced by the delimiter
+    /// itself, escaped for use in a regex if `regex` is true; other escapes
+    /// are kept as-is.
+    fn read_delimited(&mut self, delim: char, regex: bool) -> Result<String, SedError> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error(format!("unterminated `{delim}`-delimited text"))),
+                Some(c) if c == delim => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some(c) if c == delim && regex => s.push_str(&regex::escape(&c.to_string())),
+                    Some(c) if c == delim => s.push(c),
+                    Some(c) => {
+                        s.push('\\');
+                        s.push(c);
+                    }
+                    None => {
+                        return Err(self.error(format!("unterminated `{delim}`-delimited text")));
+                    }
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn build_regex(&self, pattern: &str, case_insensitive: bool) -> Result<Regex, SedError> {
+        if pattern.is_empty() {
+            return Err(self.error("empty regular expressions are not supported"));
+        }
+        RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| self.error(format!("invalid regular expression: {e}")))
+    }
+
+    /// Parse the text argument of an `a`, `i`, or `c` command
+    fn parse_text(&mut self, cmd: char) -> Result<Vec<u8>, SedError> {
+        self.skip_blanks();
+        if self.peek() == Some('\\') {
+            self.next();
+            if self.peek() == Some('\n') {
+                self.next();
+            } else {
+                self.skip_blanks();
+            }
+        }
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => break,
+                Some('\\') => match self.next() {
+                    Some(c) => text.push(c),
+                    None => break,
+                },
+                Some(c) => text.push(c),
+            }
+        }
+        if text.is_empty() {
+            return Err(self.error(format!("expected text after `{cmd}`")));
+        }
+        Ok(text.into_bytes())
+    }
+}
+
+/// Parse the replacement of an `s` command
+fn parse_replacement(s: &str) -> Vec<ReplacementPart> {
+    let mut parts = Vec::new();
+    let mut literal = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let group = match c {
+            '&' => Some(0),
+            '\\' => match chars.next() {
+                Some(d @ '0'..='9') => d.to_digit(10).map(|d| d as usize),
+                Some('n') => {
+                    literal.push(b'\n');
+                    None
+                }
+                Some('t') => {
+                    literal.push(b'\t');
+                    None
+                }
+                Some(c) => {
+                    let mut buf = [0; 4];
+                    literal.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    None
+                }
+                None => {
+                    literal.push(b'\\');
+                    None
+                }
+            },
+            c => {
+                let mut buf = [0; 4];
+                literal.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                None
+            }
+        };
+        if let Some(i) = group {
+            if !literal.is_empty() {
+                parts.push(ReplacementPart::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(ReplacementPart::Group(i));
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(ReplacementPart::Literal(literal));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests;
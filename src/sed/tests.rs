@@ -0,0 +1,106 @@
+use super::SedScript;
+
+fn sed(script: &str, input: &str) -> String {
+    let script = SedScript::parse(script).expect("script should be valid");
+    let mut out = Vec::new();
+    script
+        .execute(input.as_bytes(), &mut out)
+        .expect("script should run");
+    String::from_utf8(out).expect("output should be UTF-8")
+}
+
+const TEXT: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+#[test]
+fn run_script() {
+    for (script, output) in [
+        ("s/o/0/", "0ne\ntw0\nthree\nf0ur\nfive\n"),
+        ("s/e/E/g", "onE\ntwo\nthrEE\nfour\nfivE\n"),
+        ("s/e/E/2", "one\ntwo\nthreE\nfour\nfive\n"),
+        ("s/E/_/I", "on_\ntwo\nthr_e\nfour\nfiv_\n"),
+        (r"s/(.)(.)/\2\1/", "noe\nwto\nhtree\nofur\nifve\n"),
+        ("s/o/[&]/g", "[o]ne\ntw[o]\nthree\nf[o]ur\nfive\n"),
+        (r"s|o|\||", "|ne\ntw|\nthree\nf|ur\nfive\n"),
+        (r"s/^t/\n/", "one\n\nwo\n\nhree\nfour\nfive\n"),
+        ("2d", "one\nthree\nfour\nfive\n"),
+        ("$d", "one\ntwo\nthree\nfour\n"),
+        ("/^t/d", "one\nfour\nfive\n"),
+        ("/^t/!d", "two\nthree\n"),
+        ("2,4d", "one\nfive\n"),
+        ("/two/,/four/d", "one\nfive\n"),
+        ("3,1d", "one\ntwo\nfour\nfive\n"),
+        ("4,$d", "one\ntwo\nthree\n"),
+        ("2p", "one\ntwo\ntwo\nthree\nfour\nfive\n"),
+        ("#n\n/f/p", "four\nfive\n"),
+        ("#n\ns/o/0/p", "0ne\ntw0\nf0ur\n"),
+        (
+            "1i start\n$a end",
+            "start\none\ntwo\nthree\nfour\nfive\nend\n",
+        ),
+        (
+            "3a\\\n  indented\\\nmore",
+            "one\ntwo\nthree\n  indented\nmore\nfour\nfive\n",
+        ),
+        ("2,3c gone", "one\ngone\nfour\nfive\n"),
+        ("/^f/c F", "one\ntwo\nthree\nF\nF\n"),
+        ("1h;1d;$G", "two\nthree\nfour\nfive\none\n"),
+        ("1!G;h;$!d", "five\nfour\nthree\ntwo\none\n"),
+        ("x;1d;$G", "one\ntwo\nthree\nfour\nfive\n"),
+        ("$=", "one\ntwo\nthree\nfour\n5\nfive\n"),
+        (
+            "# a comment\n s/one/1/ ; s/two/2/ # another",
+            "1\n2\nthree\nfour\nfive\n",
+        ),
+    ] {
+        assert_eq!(sed(script, TEXT), output, "script: {script:?}");
+    }
+}
+
+#[test]
+fn missing_final_newline() {
+    assert_eq!(sed("s/b/B/", "a\nb"), "a\nB");
+    assert_eq!(sed("p", "a\nb"), "a\na\nb\nb");
+    assert_eq!(sed("$a end", "a\nb"), "a\nb\nend\n");
+}
+
+#[test]
+fn quiet() {
+    let mut script = SedScript::parse("2p").unwrap();
+    script.quiet(true);
+    let mut out = Vec::new();
+    script.execute(TEXT.as_bytes(), &mut out).unwrap();
+    assert_eq!(out, b"two\n");
+}
+
+#[test]
+fn non_utf8() {
+    let script = SedScript::parse("s/a/b/").unwrap();
+    let mut out = Vec::new();
+    script.execute(&b"\xFFa\n"[..], &mut out).unwrap();
+    assert_eq!(out, b"\xFFb\n");
+}
+
+#[test]
+fn parse_error() {
+    for (script, message) in [
+        ("k", "line 1: unknown or unsupported command: `k`"),
+        ("p\ns/a/b", "line 2: unterminated `/`-delimited text"),
+        ("s/(/x/", "line 1: invalid regular expression"),
+        (
+            "s//x/",
+            "line 1: empty regular expressions are not supported",
+        ),
+        ("0d", "line 1: invalid line number"),
+        ("1,", "line 1: expected address after `,`"),
+        ("dp", "line 1: extra characters after command: `p`"),
+        ("1", "line 1: missing command"),
+        ("a", "line 1: expected text after `a`"),
+        ("{p}", "line 1: unknown or unsupported command: `{`"),
+    ] {
+        let e = SedScript::parse(script).unwrap_err();
+        assert!(
+            e.to_string().starts_with(message),
+            "{e:?} does not start with {message:?}"
+        );
+    }
+}
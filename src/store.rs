@@ -0,0 +1,301 @@
+use crate::{Backup, FileIdentity, InPlace, InPlaceError};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions, create_dir_all, metadata, read, symlink_metadata};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::Builder;
+
+/// A version of an edited file that was backed up to a [`Backup::Store`]
+/// backup store.  See [`InPlace::list_versions()`].
+#[cfg_attr(docsrs, doc(cfg(feature = "store")))]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StoredVersion {
+    id: String,
+    timestamp: SystemTime,
+    blob: PathBuf,
+}
+
+impl StoredVersion {
+    /// The identifier of the version, which is the lowercase hex-encoded
+    /// SHA-256 digest of its contents.  Pass this to
+    /// [`InPlace::restore_version()`] in order to restore the version.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The time at which the version was backed up
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The path within the backup store at which the version's contents are
+    /// stored.  As identical contents are only stored once, this file may be
+    /// shared with other versions and other edited files, and so it should
+    /// not be modified.
+    pub fn path(&self) -> &Path {
+        &self.blob
+    }
+}
+
+impl InPlace {
+    /// List the versions of the edited path that have been backed up to the
+    /// [`Backup::Store`] backup store, oldest first.
+    ///
+    /// The edited path is resolved as though `create` were set, so it need
+    /// not exist.  Malformed entries in the store's index are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::NoBackup`] if the backup
+    /// mode is not [`Backup::Store`].  Returns an error of kind
+    /// [`InPlaceErrorKind::ReadStore`] if reading the store's index fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for
+    /// the other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::NoBackup`]: crate::InPlaceErrorKind::NoBackup
+    /// [`InPlaceErrorKind::ReadStore`]: crate::InPlaceErrorKind::ReadStore
+    pub fn list_versions(&self) -> Result<Vec<StoredVersion>, InPlaceError> {
+        let Some(Backup::Store(dir)) = self.backup.as_ref() else {
+            return Err(InPlaceError::no_backup());
+        };
+        let mut inp = self.clone();
+        inp.create = true;
+        let (path, _) = inp.resolve()?;
+        BackupStore(dir)
+            .versions(&path)
+            .map_err(InPlaceError::read_store)
+    }
+
+    /// Replace the contents of the edited path with the version with the
+    /// given ID from the [`Backup::Store`] backup store.
+    ///
+    /// The version is written by opening the edited path with the same
+    /// settings as `self` (except that `create` is set and `create_new` is
+    /// unset) and then saving it, so the edited file's current contents (if
+    /// any) are backed up to the store first, and restoring can itself be
+    /// undone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::NoBackup`] if the backup
+    /// mode is not [`Backup::Store`] or if no version of the edited path with
+    /// the given ID exists in the store.  Returns an error of kind
+    /// [`InPlaceErrorKind::Restore`] if reading the stored version fails.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for
+    /// the other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::NoBackup`]: crate::InPlaceErrorKind::NoBackup
+    /// [`InPlaceErrorKind::Restore`]: crate::InPlaceErrorKind::Restore
+    pub fn restore_version(&self, id: &str) -> Result<(), InPlaceError> {
+        let version = self
+            .list_versions()?
+            .into_iter()
+            .find(|v| v.id == id)
+            .ok_or_else(InPlaceError::no_backup)?;
+        self.write_version(&version)
+    }
+
+    /// Implementation of [`InPlace::restore()`] for [`Backup::Store`]:
+    /// restore the most recently stored version of `path`, provided that
+    /// `path` has not been modified since then (as determined by comparing
+    /// against `saved`, if given)
+    pub(crate) fn restore_latest(
+        &self,
+        path: &Path,
+        saved: Option<&FileIdentity>,
+    ) -> Result<PathBuf, InPlaceError> {
+        let version = self
+            .list_versions()?
+            .pop()
+            .ok_or_else(InPlaceError::no_backup)?;
+        match symlink_metadata(path) {
+            Ok(md) => {
+                let modified = match saved {
+                    Some(ident) => ident.differs_from(&md),
+                    None => md.modified().map_err(InPlaceError::get_metadata)? > version.timestamp,
+                };
+                if modified {
+                    return Err(InPlaceError::modified());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(InPlaceError::get_metadata(e)),
+        }
+        self.write_version(&version)?;
+        Ok(version.blob)
+    }
+
+    fn write_version(&self, version: &StoredVersion) -> Result<(), InPlaceError> {
+        let mut src = File::open(&version.blob).map_err(InPlaceError::restore)?;
+        let mut inp = self.clone();
+        inp.create = true;
+        inp.create_new = false;
+        let file = inp.open()?;
+        io::copy(&mut src, &mut file.writer()).map_err(InPlaceError::restore)?;
+        file.save()
+    }
+}
+
+/// A directory in which backups are stored under the SHA-256 digests of
+/// their contents, along with an index recording which paths were backed up
+/// when.
+///
+/// Stored contents live at `{dir}/objects/{digest}`, while the index is a
+/// file at `{dir}/index` containing one line per backup of the form
+/// `{seconds}.{nanoseconds}\t{digest}\t{path}`, where `{path}` is the
+/// absolute edited path with `%`, tab, and newline characters
+/// percent-encoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct BackupStore<'a>(pub(crate) &'a Path);
+
+impl BackupStore<'_> {
+    /// Store a copy of the contents of `path` (unless identical contents are
+    /// already stored), record it in the index, and return the path to the
+    /// stored copy
+    pub(crate) fn add(self, path: &Path) -> io::Result<PathBuf> {
+        let mut src = File::open(path)?;
+        let objects = self.0.join("objects");
+        create_dir_all(&objects)?;
+        let mut writer = HashingWriter {
+            inner: Builder::new().prefix("._in_place-").tempfile_in(&objects)?,
+            hasher: Sha256::new(),
+        };
+        io::copy(&mut src, &mut writer)?;
+        drop(src);
+        let id = hex(&writer.hasher.finalize());
+        let blob = objects.join(&id);
+        match writer.inner.persist_noclobber(&blob) {
+            Ok(_) => (),
+            // The contents are already stored; the temporary file is deleted
+            // when the error is dropped.
+            Err(e) if e.error.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e.error),
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        let mut line = format!(
+            "{}.{:09}\t{id}\t",
+            timestamp.as_secs(),
+            timestamp.subsec_nanos()
+        )
+        .into_bytes();
+        escape_path(path, &mut line);
+        line.push(b'\n');
+        // The whole line is written at once so that concurrent appends do
+        // not interleave.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.0.join("index"))?
+            .write_all(&line)?;
+        Ok(blob)
+    }
+
+    /// Return all stored versions of `path`, in the order that they were
+    /// added
+    fn versions(self, path: &Path) -> io::Result<Vec<StoredVersion>> {
+        let index = match read(self.0.join("index")) {
+            Ok(index) => index,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let target = path.as_os_str().as_encoded_bytes();
+        let objects = self.0.join("objects");
+        Ok(index
+            .split(|&b| b == b'\n')
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, |&b| b == b'\t');
+                let timestamp = parse_timestamp(fields.next()?)?;
+                let id = std::str::from_utf8(fields.next()?).ok()?;
+                if id.len() != 64 || !id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+                    return None;
+                }
+                (unescape_path(fields.next()?)? == target).then(|| StoredVersion {
+                    id: id.to_owned(),
+                    timestamp,
+                    blob: objects.join(id),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Replace `path` with a copy of the stored contents at `blob` via a
+/// temporary file, keeping the permissions of the file currently at `path`
+pub(crate) fn copy_blob_into_place(blob: &Path, path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(p) if p != Path::new("") => p,
+        _ => Path::new("."),
+    };
+    let mut src = File::open(blob)?;
+    let mut tmp = Builder::new().prefix("._in_place-").tempfile_in(dir)?;
+    io::copy(&mut src, tmp.as_file_mut())?;
+    if let Ok(md) = metadata(path) {
+        tmp.as_file().set_permissions(md.permissions())?;
+    }
+    tmp.persist(path).map(drop).map_err(|e| e.error)
+}
+
+/// A writer that computes the SHA-256 digest of everything written to it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(s, "{b:02x}");
+    }
+    s
+}
+
+fn parse_timestamp(field: &[u8]) -> Option<SystemTime> {
+    let (secs, nanos) = std::str::from_utf8(field).ok()?.split_once('.')?;
+    let nanos = nanos.parse().ok().filter(|&n| n < 1_000_000_000)?;
+    UNIX_EPOCH.checked_add(Duration::new(secs.parse().ok()?, nanos))
+}
+
+fn escape_path(path: &Path, buf: &mut Vec<u8>) {
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if matches!(b, b'%' | b'\t' | b'\n') {
+            buf.extend_from_slice(format!("%{b:02X}").as_bytes());
+        } else {
+            buf.push(b);
+        }
+    }
+}
+
+fn unescape_path(field: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut iter = field.iter();
+    while let Some(&b) = iter.next() {
+        if b == b'%' {
+            let hi = char::from(*iter.next()?).to_digit(16)?;
+            let lo = char::from(*iter.next()?).to_digit(16)?;
+            out.push(u8::try_from(hi * 16 + lo).ok()?);
+        } else {
+            out.push(b);
+        }
+    }
+    Some(out)
+}
@@ -1,9 +1,12 @@
-use super::{Backup, InPlace, InPlaceErrorKind};
+use super::{
+    AtomicWrite, Backup, BackupMethod, ForEachError, InPlace, InPlaceErrorKind, OnError,
+    PathOutcome,
+};
 use assert_fs::fixture::TempDir;
 use assert_fs::prelude::*;
 use serial_test::serial;
 use std::fs::{read_dir, read_link, remove_file};
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Component, Path, PathBuf};
 use tmp_env::set_current_dir;
 
@@ -1372,3 +1375,2465 @@ fn append_no_filename_nofollow() {
     assert!(e.as_io_error().is_none());
     assert!(listdir(&tmpdir).unwrap().is_empty());
 }
+
+#[test]
+fn temp_prefix_suffix() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let inp = InPlace::new(&p)
+            .temp_prefix("edit-")
+            .temp_suffix(".txt")
+            .temp_rand_len(10)
+            .open()
+            .unwrap();
+        let temp_path = inp.temp_path().to_path_buf();
+        assert_eq!(
+            temp_path.parent(),
+            Some(tmpdir.canonicalize().unwrap().as_path())
+        );
+        let fname = temp_path.file_name().unwrap().to_str().unwrap();
+        assert!(fname.starts_with("edit-"), "{fname:?} lacks prefix");
+        assert!(fname.ends_with(".txt"), "{fname:?} lacks suffix");
+        assert_eq!(fname.len(), "edit-".len() + 10 + ".txt".len());
+        assert_eq!(listdir(&tmpdir).unwrap(), [fname, "file.txt"]);
+        let reader = BufReader::new(inp.reader());
+        let mut writer = inp.writer();
+        for line in reader.lines() {
+            writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        inp.save().unwrap();
+        assert!(!temp_path.exists());
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn temp_template_name() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .temp_template(".{name}.{rand}.tmp")
+        .open()
+        .unwrap();
+    let fname = inp.temp_path().file_name().unwrap().to_str().unwrap();
+    assert!(fname.starts_with(".file.txt."), "{fname:?} lacks prefix");
+    assert!(fname.ends_with(".tmp"), "{fname:?} lacks suffix");
+    assert_eq!(fname.len(), ".file.txt.".len() + 6 + ".tmp".len());
+    inp.discard().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn temp_template_no_rand() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .temp_suffix(".old")
+        .temp_template("{name}~")
+        .open()
+        .unwrap();
+    let fname = inp.temp_path().file_name().unwrap().to_str().unwrap();
+    assert!(fname.starts_with("file.txt~"), "{fname:?} lacks prefix");
+    assert_eq!(fname.len(), "file.txt~".len() + 6);
+    inp.discard().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+}
+
+#[test]
+fn temp_name_no_filename_nofollow() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("..");
+    let r = InPlace::new(p)
+        .temp_prefix("{name}-")
+        .follow_symlinks(false)
+        .open();
+    assert!(r.is_err());
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoFilename);
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn defer_permissions() {
+    use std::fs::{metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(&p, PermissionsExt::from_mode(0o755)).unwrap();
+    {
+        let inp = InPlace::new(&p).defer_permissions(true).open().unwrap();
+        let md = metadata(inp.temp_path()).unwrap();
+        assert_eq!(md.mode() & 0o777, 0o600);
+        let reader = BufReader::new(inp.reader());
+        let mut writer = inp.writer();
+        for line in reader.lines() {
+            writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o755);
+}
+
+#[cfg(unix)]
+#[test]
+fn defer_permissions_readonly() {
+    use std::fs::{OpenOptions, metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(&p, PermissionsExt::from_mode(0o444)).unwrap();
+    {
+        let inp = InPlace::new(&p).defer_permissions(true).open().unwrap();
+        // The temporary file can still be reopened for writing:
+        let mut reopened = OpenOptions::new()
+            .append(true)
+            .open(inp.temp_path())
+            .unwrap();
+        reopened.write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        drop(reopened);
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o444);
+}
+
+#[cfg(unix)]
+#[test]
+fn defer_permissions_discard() {
+    use std::fs::{metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(&p, PermissionsExt::from_mode(0o644)).unwrap();
+    {
+        let inp = InPlace::new(&p).defer_permissions(true).open().unwrap();
+        writeln!(inp.writer(), "This will be discarded.\n").unwrap();
+        inp.discard().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o644);
+}
+
+#[cfg(unix)]
+#[test]
+fn explicit_mode() {
+    use std::fs::{metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(&p, PermissionsExt::from_mode(0o644)).unwrap();
+    {
+        let inp = InPlace::new(&p).mode(0o640).open().unwrap();
+        let md = metadata(inp.temp_path()).unwrap();
+        assert_eq!(md.mode() & 0o777, 0o640);
+        let reader = BufReader::new(inp.reader());
+        let mut writer = inp.writer();
+        for line in reader.lines() {
+            writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn explicit_mode_deferred() {
+    use std::fs::{metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(&p, PermissionsExt::from_mode(0o644)).unwrap();
+    {
+        let inp = InPlace::new(&p)
+            .mode(0o755)
+            .defer_permissions(true)
+            .open()
+            .unwrap();
+        let md = metadata(inp.temp_path()).unwrap();
+        assert_eq!(md.mode() & 0o777, 0o600);
+        writeln!(inp.writer(), "#!/bin/sh").unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert("#!/bin/sh\n");
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o755);
+}
+
+#[test]
+fn create_missing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p).create(true).open().unwrap();
+        assert_eq!(inp.path(), tmpdir.canonicalize().unwrap().join("file.txt"));
+        let mut contents = String::new();
+        Read::read_to_string(&mut inp.reader(), &mut contents).unwrap();
+        assert_eq!(contents, "");
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+#[serial]
+fn create_missing_relative() {
+    let tmpdir = TempDir::new().unwrap();
+    let _chdir = set_current_dir(&tmpdir);
+    {
+        let inp = InPlace::new("file.txt").create(true).open().unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    tmpdir.child("file.txt").assert(TEXT);
+}
+
+#[test]
+fn create_existing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let inp = InPlace::new(&p)
+            .create(true)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        let reader = BufReader::new(inp.reader());
+        let mut writer = inp.writer();
+        for line in reader.lines() {
+            writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn create_missing_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create(true)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn create_missing_discard() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p).create(true).open().unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.discard().unwrap();
+    }
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[test]
+fn create_missing_nofollow() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create(true)
+            .follow_symlinks(false)
+            .open()
+            .unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn create_broken_symlink() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let target = tmpdir.child("nowhere.txt");
+    if !mklink(&target, &p).unwrap() {
+        return;
+    }
+    let r = InPlace::new(&p).create(true).open();
+    assert!(r.is_err());
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Canonicalize);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn create_default_mode() {
+    use std::fs::metadata;
+    use std::os::unix::fs::MetadataExt;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create(true)
+            .default_mode(0o640)
+            .open()
+            .unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    p.assert(TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn create_umask_mode_deferred() {
+    use std::fs::{OpenOptions, metadata};
+    use std::os::unix::fs::MetadataExt;
+    let tmpdir = TempDir::new().unwrap();
+    // Determine what mode the umask gives to new files
+    let probe = tmpdir.child("probe.txt");
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .unwrap();
+    let expected = metadata(&probe).unwrap().mode() & 0o777;
+    remove_file(&probe).unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create(true)
+            .defer_permissions(true)
+            .open()
+            .unwrap();
+        let md = metadata(inp.temp_path()).unwrap();
+        assert_eq!(md.mode() & 0o777, 0o600);
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    p.assert(TEXT);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, expected);
+}
+
+#[test]
+fn create_new_missing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create_new(true)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        inp.writer().write_all(TEXT.as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn create_new_exists() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let r = InPlace::new(&p).create_new(true).open();
+    assert!(r.is_err());
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::AlreadyExists);
+    assert_eq!(e.to_string(), "file already exists");
+    assert!(e.as_io_error().is_none());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn create_new_appears_before_save() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let inp = InPlace::new(&p)
+            .create_new(true)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        p.write_str(TEXT).unwrap();
+        let r = inp.save();
+        assert!(r.is_err());
+        let e = r.unwrap_err();
+        assert_eq!(e.kind(), InPlaceErrorKind::PersistTemp);
+        assert_eq!(
+            e.as_io_error().map(io::Error::kind),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn atomic_write() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let aw = AtomicWrite::new(&p).open().unwrap();
+        assert_eq!(aw.path(), p.canonicalize().unwrap());
+        assert_eq!(aw.backup_path(), None);
+        aw.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        aw.commit().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn atomic_write_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let aw = AtomicWrite::new(&p)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        aw.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        aw.commit().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn atomic_write_abort() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let aw = AtomicWrite::new(&p)
+            .backup(Backup::Append("~".into()))
+            .open()
+            .unwrap();
+        aw.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        aw.abort().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn atomic_write_missing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    {
+        let aw = AtomicWrite::new(&p).open().unwrap();
+        aw.writer().write_all(TEXT.as_bytes()).unwrap();
+        aw.commit().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn atomic_write_missing_nocreate() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let r = AtomicWrite::new(&p).create(false).open();
+    assert!(r.is_err());
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Canonicalize);
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn atomic_write_unreadable_file() {
+    use std::fs::{metadata, set_permissions};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    set_permissions(p.path(), PermissionsExt::from_mode(0o200)).unwrap();
+    {
+        let aw = AtomicWrite::new(&p).open().unwrap();
+        aw.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        aw.commit().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    let md = metadata(&p).unwrap();
+    assert_eq!(md.mode() & 0o777, 0o200);
+    set_permissions(p.path(), PermissionsExt::from_mode(0o644)).unwrap();
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn read_write_impls() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let mut inp = InPlace::new(&p).open().unwrap();
+        let mut contents = String::new();
+        Read::read_to_string(&mut inp, &mut contents).unwrap();
+        assert_eq!(contents, TEXT);
+        inp.write_all(swapcase(&contents).as_bytes()).unwrap();
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn lines_ref_write() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let inp = InPlace::new(&p).open().unwrap();
+        for line in inp.lines() {
+            writeln!(&inp, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn buffered_lines() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let inp = InPlace::new(&p).buffered(true).open().unwrap();
+        for line in inp.lines() {
+            writeln!(&inp, "{}", swapcase(&line.unwrap())).unwrap();
+        }
+        // Nothing has been flushed to the temporary file yet:
+        assert_eq!(inp.writer().metadata().unwrap().len(), 0);
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn buffered_read_remaining() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let mut inp = InPlace::new(&p).buffered(true).open().unwrap();
+        let contents = inp.read_remaining().unwrap();
+        assert_eq!(contents, TEXT);
+        assert_eq!(inp.read_remaining().unwrap(), "");
+        let mut s = String::from("x");
+        assert_eq!(inp.read_to_string(&mut s).unwrap(), 0);
+        assert_eq!(s, "x");
+        write!(inp, "{}", swapcase(&contents)).unwrap();
+        inp.flush().unwrap();
+        assert_eq!(
+            inp.writer().metadata().unwrap().len(),
+            u64::try_from(SWAPPED_TEXT.len()).unwrap()
+        );
+        inp.save().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn buffered_discard() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let mut inp = InPlace::new(&p).buffered(true).open().unwrap();
+        writeln!(inp, "This will be discarded.").unwrap();
+        inp.discard().unwrap();
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn buffered_drop() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    {
+        let mut inp = InPlace::new(&p).buffered(true).open().unwrap();
+        writeln!(inp, "This will be discarded.").unwrap();
+        drop(inp);
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn for_each_path() {
+    let tmpdir = TempDir::new().unwrap();
+    let p1 = tmpdir.child("one.txt");
+    p1.write_str(TEXT).unwrap();
+    let p2 = tmpdir.child("two.txt");
+    p2.write_str(SWAPPED_TEXT).unwrap();
+    let report = InPlace::new("")
+        .backup(Backup::Append(".bak".into()))
+        .for_each_path([&p1, &p2], OnError::Abort, |inp| {
+            for line in inp.lines() {
+                writeln!(inp.writer(), "{}", swapcase(&line?))?;
+            }
+            Ok::<(), io::Error>(())
+        });
+    assert!(report.is_success());
+    assert!(!report.aborted());
+    let results = report.into_results();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, p1.path());
+    assert!(matches!(results[0].1, PathOutcome::Saved));
+    assert_eq!(results[1].0, p2.path());
+    assert!(matches!(results[1].1, PathOutcome::Saved));
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["one.txt", "one.txt.bak", "two.txt", "two.txt.bak"]
+    );
+    p1.assert(SWAPPED_TEXT);
+    tmpdir.child("one.txt.bak").assert(TEXT);
+    p2.assert(TEXT);
+    tmpdir.child("two.txt.bak").assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn for_each_path_abort() {
+    let tmpdir = TempDir::new().unwrap();
+    let p1 = tmpdir.child("one.txt");
+    p1.write_str(TEXT).unwrap();
+    let missing = tmpdir.child("missing.txt");
+    let p2 = tmpdir.child("two.txt");
+    p2.write_str(TEXT).unwrap();
+    let report = InPlace::new("").for_each_path([&p1, &missing, &p2], OnError::Abort, |inp| {
+        inp.writer().write_all(SWAPPED_TEXT.as_bytes())
+    });
+    assert!(!report.is_success());
+    assert!(report.aborted());
+    let results = report.results();
+    assert_eq!(results.len(), 2);
+    assert!(matches!(results[0].1, PathOutcome::Saved));
+    assert_eq!(results[1].0, missing.path());
+    match &results[1].1 {
+        PathOutcome::Failed(ForEachError::InPlace(e)) => {
+            assert_eq!(e.kind(), InPlaceErrorKind::Canonicalize);
+        }
+        r => panic!("Unexpected outcome: {r:?}"),
+    }
+    p1.assert(SWAPPED_TEXT);
+    p2.assert(TEXT);
+}
+
+#[test]
+fn for_each_path_skip() {
+    let tmpdir = TempDir::new().unwrap();
+    let p1 = tmpdir.child("one.txt");
+    p1.write_str(TEXT).unwrap();
+    let missing = tmpdir.child("missing.txt");
+    let p2 = tmpdir.child("two.txt");
+    p2.write_str(TEXT).unwrap();
+    let report = InPlace::new("").for_each_path([&p1, &missing, &p2], OnError::Skip, |inp| {
+        inp.writer().write_all(SWAPPED_TEXT.as_bytes())
+    });
+    assert!(!report.is_success());
+    assert!(!report.aborted());
+    let results = report.results();
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[0].1, PathOutcome::Saved));
+    assert!(matches!(results[1].1, PathOutcome::Skipped));
+    assert!(matches!(results[2].1, PathOutcome::Saved));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["one.txt", "two.txt"]);
+    p1.assert(SWAPPED_TEXT);
+    p2.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn for_each_path_collect_callback_error() {
+    let tmpdir = TempDir::new().unwrap();
+    let p1 = tmpdir.child("one.txt");
+    p1.write_str(TEXT).unwrap();
+    let p2 = tmpdir.child("two.txt");
+    p2.write_str(TEXT).unwrap();
+    let report = InPlace::new("")
+        .backup(Backup::Append(".bak".into()))
+        .for_each_path([&p1, &p2], OnError::Collect, |inp| {
+            inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+            if inp.path().ends_with("one.txt") {
+                Err("refusing to edit one.txt")
+            } else {
+                Ok(())
+            }
+        });
+    assert!(!report.is_success());
+    let results = report.into_results();
+    assert_eq!(results.len(), 2);
+    match &results[0].1 {
+        PathOutcome::Failed(e @ ForEachError::Callback(_)) => {
+            assert_eq!(e.to_string(), "refusing to edit one.txt");
+        }
+        r => panic!("Unexpected outcome: {r:?}"),
+    }
+    assert!(matches!(results[1].1, PathOutcome::Saved));
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["one.txt", "two.txt", "two.txt.bak"]
+    );
+    p1.assert(TEXT);
+    p2.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn for_each_path_backup_path() {
+    let tmpdir = TempDir::new().unwrap();
+    let p1 = tmpdir.child("one.txt");
+    p1.write_str(TEXT).unwrap();
+    let p2 = tmpdir.child("two.txt");
+    p2.write_str(TEXT).unwrap();
+    let report = InPlace::new("")
+        .backup(Backup::Path(tmpdir.child("backup.txt").to_path_buf()))
+        .for_each_path([&p1, &p2], OnError::Collect, |inp| {
+            inp.writer().write_all(SWAPPED_TEXT.as_bytes())
+        });
+    assert!(!report.is_success());
+    let results = report.results();
+    assert_eq!(results.len(), 2);
+    for (_, outcome) in results {
+        match outcome {
+            PathOutcome::Failed(ForEachError::InPlace(e)) => {
+                assert_eq!(e.kind(), InPlaceErrorKind::SharedBackup);
+            }
+            r => panic!("Unexpected outcome: {r:?}"),
+        }
+    }
+    assert_eq!(listdir(&tmpdir).unwrap(), ["one.txt", "two.txt"]);
+    p1.assert(TEXT);
+    p2.assert(TEXT);
+}
+
+#[test]
+fn dry_run() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .dry_run(true)
+        .open()
+        .unwrap();
+    let reader = BufReader::new(inp.reader());
+    let mut writer = inp.writer();
+    for line in reader.lines() {
+        writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+    }
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn dry_run_create() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let inp = InPlace::new(&p).create(true).dry_run(true).open().unwrap();
+    inp.writer().write_all(TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[cfg(feature = "diff")]
+#[test]
+fn diff() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).dry_run(true).open().unwrap();
+    let mut lines = inp.lines().map(Result::unwrap).collect::<Vec<_>>();
+    lines[2] = lines[2].to_uppercase();
+    for line in lines {
+        writeln!(inp.writer(), "{line}").unwrap();
+    }
+    let name = inp.path().display().to_string();
+    assert_eq!(
+        inp.diff(1).unwrap(),
+        format!(
+            concat!(
+                "--- {name}\n",
+                "+++ {name}\n",
+                "@@ -2,3 +2,3 @@\n",
+                " \tDid gyre and gimble in the wabe;\n",
+                "-All mimsy were the borogoves,\n",
+                "+ALL MIMSY WERE THE BOROGOVES,\n",
+                " \tAnd the mome raths outgrabe.\n",
+            ),
+            name = name
+        )
+    );
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "diff")]
+#[test]
+fn diff_replaced_while_open() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).open().unwrap();
+    let mut first = vec![0; TEXT.find('\n').unwrap() + 1];
+    Read::read_exact(&mut inp.reader(), &mut first).unwrap();
+    let first = String::from_utf8(first).unwrap();
+    let mut writer = inp.writer();
+    write!(writer, "{}", first.to_uppercase()).unwrap();
+    let other = tmpdir.child("other.txt");
+    other.write_str("Something else entirely\n").unwrap();
+    std::fs::rename(&other, &p).unwrap();
+    let name = inp.path().display().to_string();
+    assert_eq!(
+        inp.diff(0).unwrap(),
+        format!(
+            concat!(
+                "--- {name}\n",
+                "+++ {name}\n",
+                "@@ -1,4 +1 @@\n",
+                "-'Twas brillig, and the slithy toves\n",
+                "-\tDid gyre and gimble in the wabe;\n",
+                "-All mimsy were the borogoves,\n",
+                "-\tAnd the mome raths outgrabe.\n",
+                "+'TWAS BRILLIG, AND THE SLITHY TOVES\n",
+            ),
+            name = name
+        )
+    );
+    // The positions of both file handles are unchanged:
+    let mut rest = String::new();
+    Read::read_to_string(&mut inp.reader(), &mut rest).unwrap();
+    assert_eq!(rest, &TEXT[first.len()..]);
+    writeln!(writer, "END").unwrap();
+    inp.save().unwrap();
+    p.assert("'TWAS BRILLIG, AND THE SLITHY TOVES\nEND\n");
+}
+
+#[cfg(feature = "diff")]
+#[test]
+fn diff_unchanged() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).buffered(true).open().unwrap();
+    io::copy(&mut &inp, &mut &inp).unwrap();
+    assert_eq!(inp.diff(3).unwrap(), "");
+    inp.discard().unwrap();
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "diff")]
+#[test]
+fn diff_create() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let inp = InPlace::new(&p).create(true).buffered(true).open().unwrap();
+    writeln!(&inp, "hello").unwrap();
+    let name = inp.path().display().to_string();
+    assert_eq!(
+        inp.diff(3).unwrap(),
+        format!("--- {name}\n+++ {name}\n@@ -0,0 +1 @@\n+hello\n")
+    );
+    inp.discard().unwrap();
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[test]
+fn validate_ok() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .buffered(true)
+        .validate(|path| {
+            let content = std::fs::read_to_string(path)?;
+            assert_eq!(content, SWAPPED_TEXT);
+            Ok::<(), io::Error>(())
+        })
+        .open()
+        .unwrap();
+    for line in inp.lines() {
+        writeln!(&inp, "{}", swapcase(&line.unwrap())).unwrap();
+    }
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn validate_fail() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .validate(|_| Err("contents are invalid"))
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let r = inp.save();
+    assert!(r.is_err());
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::ValidationFailed);
+    assert_eq!(e.to_string(), "validation of new file contents failed");
+    assert_eq!(
+        std::error::Error::source(&e).unwrap().to_string(),
+        "contents are invalid"
+    );
+    assert!(e.as_io_error().is_none());
+    assert_eq!(
+        e.into_validation_error().unwrap().to_string(),
+        "contents are invalid"
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn validate_dry_run() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .dry_run(true)
+        .validate(|_| Err("contents are invalid"))
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let e = inp.save().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::ValidationFailed);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn atomic_write_validate_fail() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let aw = AtomicWrite::new(&p)
+        .validate(|path| {
+            if std::fs::metadata(path)?.len() == 0 {
+                Err(io::Error::other("file is empty"))
+            } else {
+                Ok(())
+            }
+        })
+        .open()
+        .unwrap();
+    let e = aw.commit().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::ValidationFailed);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn save_revertible_revert() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).open().unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    p.assert(SWAPPED_TEXT);
+    let hidden = saved.backup_path().unwrap().to_path_buf();
+    let files = listdir(&tmpdir).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0], hidden.file_name().unwrap().to_str().unwrap());
+    assert!(files[0].starts_with("._in_place-backup-"));
+    assert_eq!(std::fs::read_to_string(&hidden).unwrap(), TEXT);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn save_revertible_finalize() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).open().unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap().len(), 2);
+    saved.finalize().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn save_revertible_drop() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).open().unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    drop(saved);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[test]
+fn save_revertible_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(saved.path(), p.canonicalize().unwrap());
+    assert_eq!(
+        saved.backup_path(),
+        Some(tmpdir.canonicalize().unwrap().join("file.txt~").as_path())
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn save_revertible_backup_is_edited_path() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    for backup in [
+        Backup::Path(p.to_path_buf()),
+        Backup::FileName("file.txt".into()),
+    ] {
+        let inp = InPlace::new(&p).backup(backup).open().unwrap();
+        inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+        let saved = inp.save_revertible().unwrap();
+        let bp = saved.backup_path().unwrap().to_path_buf();
+        assert_ne!(bp, p.canonicalize().unwrap());
+        assert!(
+            bp.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("._in_place-backup-")
+        );
+        p.assert(SWAPPED_TEXT);
+        saved.revert().unwrap();
+        assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+        p.assert(TEXT);
+    }
+}
+
+#[test]
+fn save_revertible_backup_finalize() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save_revertible().unwrap().finalize().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn save_revertible_created() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let inp = InPlace::new(&p).create(true).open().unwrap();
+    inp.writer().write_all(TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(saved.backup_path(), None);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    saved.revert().unwrap();
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[test]
+fn save_revertible_dry_run() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p).dry_run(true).open().unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn atomic_write_commit_revertible() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let aw = AtomicWrite::new(&p).open().unwrap();
+    aw.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = aw.commit_revertible().unwrap();
+    p.assert(SWAPPED_TEXT);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Append("~".into()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    p.assert(SWAPPED_TEXT);
+    let restored = inp.restore().unwrap();
+    assert_eq!(restored, tmpdir.canonicalize().unwrap().join("file.txt~"));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore_missing_file() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    tmpdir.child("file.txt.bak").write_str(TEXT).unwrap();
+    let restored = InPlace::new(&p)
+        .backup(Backup::Append(".bak".into()))
+        .restore()
+        .unwrap();
+    assert_eq!(
+        restored,
+        tmpdir.canonicalize().unwrap().join("file.txt.bak")
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore_no_backup_set() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let e = InPlace::new(&p).restore().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoBackup);
+    assert!(e.as_io_error().is_none());
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore_backup_missing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let e = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .restore()
+        .unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoBackup);
+    assert!(e.as_io_error().is_none());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore_numbered() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(SWAPPED_TEXT).unwrap();
+    tmpdir.child("file.txt.~1~").write_str(TEXT).unwrap();
+    let e = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .restore()
+        .unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoBackup);
+    InPlace::new(&p)
+        .backup(Backup::FileName("file.txt.~1~".into()))
+        .restore()
+        .unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_modified() {
+    use std::time::{Duration, SystemTime};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Append("~".into()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    let fp = std::fs::OpenOptions::new().append(true).open(&p).unwrap();
+    writeln!(&fp, "An extra line").unwrap();
+    fp.set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    drop(fp);
+    let e = inp.restore().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Modified);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(format!("{SWAPPED_TEXT}An extra line\n"));
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn restore_saved() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Append("~".into()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = file.save_revertible().unwrap();
+    let identity = saved.identity().unwrap();
+    saved.finalize().unwrap();
+    let restored = inp.restore_saved(&identity).unwrap();
+    assert_eq!(restored, tmpdir.canonicalize().unwrap().join("file.txt~"));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn restore_saved_replaced_with_older_file() {
+    use std::time::{Duration, SystemTime};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Append("~".into()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let identity = file.save_revertible().unwrap().identity().unwrap();
+    let other = tmpdir.child("other.txt");
+    other.write_str(&TEXT.to_uppercase()).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&other)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(3600))
+        .unwrap();
+    std::fs::rename(&other, &p).unwrap();
+    let e = inp.restore_saved(&identity).unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Modified);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(TEXT.to_uppercase());
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[cfg(unix)]
+#[test]
+fn backup_method_hardlink() {
+    use std::os::unix::fs::MetadataExt;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let ino = p.metadata().unwrap().ino();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .backup_method(BackupMethod::HardLink)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    let bkp = tmpdir.child("file.txt~");
+    bkp.assert(TEXT);
+    assert_eq!(bkp.metadata().unwrap().ino(), ino);
+    assert_eq!(bkp.metadata().unwrap().nlink(), 1);
+}
+
+#[test]
+fn backup_method_hardlink_replace_existing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let bkp = tmpdir.child("file.txt~");
+    bkp.write_str("This will be replaced.\n").unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .backup_method(BackupMethod::HardLink)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    bkp.assert(TEXT);
+}
+
+#[cfg(unix)]
+#[test]
+fn backup_method_copy() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let ino = p.metadata().unwrap().ino();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .backup_method(BackupMethod::Copy)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    let bkp = tmpdir.child("file.txt~");
+    bkp.assert(TEXT);
+    let md = bkp.metadata().unwrap();
+    assert_ne!(md.ino(), ino);
+    assert_eq!(md.permissions().mode() & 0o777, 0o640);
+}
+
+#[cfg(unix)]
+#[test]
+fn backup_method_copy_symlink() {
+    let tmpdir = TempDir::new().unwrap();
+    let realpath = tmpdir.child("real.txt");
+    realpath.write_str(TEXT).unwrap();
+    let linkpath = tmpdir.child("link.txt");
+    mklink(Path::new("real.txt"), &linkpath).unwrap();
+    let inp = InPlace::new(&linkpath)
+        .backup(Backup::Append("~".into()))
+        .backup_method(BackupMethod::Copy)
+        .follow_symlinks(false)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["link.txt", "link.txt~", "real.txt"]
+    );
+    assert!(!linkpath.is_symlink());
+    linkpath.assert(SWAPPED_TEXT);
+    assert_eq!(
+        read_link(tmpdir.child("link.txt~")).unwrap(),
+        Path::new("real.txt")
+    );
+    realpath.assert(TEXT);
+}
+
+/// `FICLONE` is not supported on tmpfs, so reflinking should fall back to
+/// copying
+#[cfg(target_os = "linux")]
+#[test]
+fn backup_method_reflink_tmpfs_fallback() {
+    use std::os::unix::fs::MetadataExt;
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() {
+        return;
+    }
+    let tmpdir = TempDir::new_in(shm).unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let ino = p.metadata().unwrap().ino();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .backup_method(BackupMethod::Reflink)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(SWAPPED_TEXT);
+    let bkp = tmpdir.child("file.txt~");
+    bkp.assert(TEXT);
+    assert_ne!(bkp.metadata().unwrap().ino(), ino);
+}
+
+/// Hard links cannot cross filesystems, so linking a file on tmpfs to a
+/// backup elsewhere should fall back to copying
+#[cfg(target_os = "linux")]
+#[test]
+fn backup_method_hardlink_cross_device_fallback() {
+    use std::os::unix::fs::MetadataExt;
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() {
+        return;
+    }
+    let tmpdir = TempDir::new_in(shm).unwrap();
+    let bkpdir = TempDir::new().unwrap();
+    if tmpdir.metadata().unwrap().dev() == bkpdir.metadata().unwrap().dev() {
+        return;
+    }
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let bkp = bkpdir.child("file.txt.bak");
+    let inp = InPlace::new(&p)
+        .backup(Backup::Path(bkp.to_path_buf()))
+        .backup_method(BackupMethod::HardLink)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    assert_eq!(listdir(&bkpdir).unwrap(), ["file.txt.bak"]);
+    p.assert(SWAPPED_TEXT);
+    bkp.assert(TEXT);
+}
+
+#[test]
+fn backup_method_revertible() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup_method(BackupMethod::HardLink)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap().len(), 2);
+    p.assert(SWAPPED_TEXT);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn compress_backup_gzip() {
+    use super::Compression;
+    use std::io::Read;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .compress_backup(Compression::Gzip)
+        .open()
+        .unwrap();
+    assert_eq!(
+        inp.backup_path().unwrap(),
+        tmpdir.canonicalize().unwrap().join("file.txt~.gz")
+    );
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~.gz"]);
+    p.assert(SWAPPED_TEXT);
+    let gz = std::fs::File::open(tmpdir.child("file.txt~.gz")).unwrap();
+    let mut s = String::new();
+    flate2::read::GzDecoder::new(gz)
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, TEXT);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn compress_backup_revert() {
+    use super::Compression;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append(".bak".into()))
+        .compress_backup(Compression::Gzip)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = inp.save_revertible().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt.bak.gz"]);
+    saved.revert().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_latin1() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"caf\xE9 na\xEFve\n").unwrap();
+    let file = InPlace::new(&p)
+        .encoding(encoding_rs::WINDOWS_1252)
+        .open_text()
+        .unwrap();
+    assert_eq!(file.encoding(), encoding_rs::WINDOWS_1252);
+    assert!(!file.had_bom());
+    assert_eq!(file.text(), "caf\u{E9} na\u{EF}ve\n");
+    file.write_str(&file.text().to_uppercase()).unwrap();
+    file.save().unwrap();
+    p.assert(&b"CAF\xC9 NA\xCFVE\n"[..]);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_utf16le_bom() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"\xFF\xFEh\x00\xE9\x00\n\x00").unwrap();
+    let file = InPlace::new(&p).open_text().unwrap();
+    assert_eq!(file.encoding(), encoding_rs::UTF_16LE);
+    assert!(file.had_bom());
+    assert_eq!(file.text(), "h\u{E9}\n");
+    file.write_str("\u{2603}").unwrap();
+    file.write_str("!\n").unwrap();
+    file.save().unwrap();
+    p.assert(&b"\xFF\xFE\x03\x26!\x00\n\x00"[..]);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_transcode() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"caf\xE9\n").unwrap();
+    let mut file = InPlace::new(&p)
+        .encoding(encoding_rs::WINDOWS_1252)
+        .output_encoding(encoding_rs::UTF_8)
+        .write_bom(true)
+        .open_text()
+        .unwrap();
+    assert_eq!(file.output_encoding(), encoding_rs::UTF_8);
+    let text = file.take_text();
+    assert_eq!(file.text(), "");
+    file.write_str(&text).unwrap();
+    file.save().unwrap();
+    p.assert("\u{FEFF}caf\u{E9}\n");
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_malformed() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"caf\xE9\n").unwrap();
+    let e = InPlace::new(&p)
+        .encoding(encoding_rs::UTF_8)
+        .open_text()
+        .unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Decode);
+    assert_eq!(e.as_io_error().unwrap().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_malformed_detected() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"caf\xE9\n").unwrap();
+    let e = InPlace::new(&p).open_text().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Decode);
+    assert_eq!(e.as_io_error().unwrap().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(&b"caf\xE9\n"[..]);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn open_text_unencodable() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_binary(b"caf\xE9\n").unwrap();
+    let file = InPlace::new(&p)
+        .encoding(encoding_rs::WINDOWS_1252)
+        .open_text()
+        .unwrap();
+    let e = file.write_str("\u{2603}\n").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    file.write_str("ok\n").unwrap();
+    file.save().unwrap();
+    p.assert("ok\n");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn open_compressed_gzip_detect() {
+    use super::Compression;
+    use std::io::Read;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("data.bin");
+    let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    enc.write_all(TEXT.as_bytes()).unwrap();
+    p.write_binary(&enc.finish().unwrap()).unwrap();
+    let file = InPlace::new(&p).open_compressed(None).unwrap();
+    assert_eq!(file.compression(), Compression::Gzip);
+    let reader = BufReader::new(&file);
+    let mut writer = &file;
+    for line in reader.lines() {
+        writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+    }
+    file.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["data.bin"]);
+    let gz = std::fs::File::open(&p).unwrap();
+    let mut s = String::new();
+    flate2::read::GzDecoder::new(gz)
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, SWAPPED_TEXT);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn open_compressed_zstd_explicit() {
+    use super::Compression;
+    use std::io::Read;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt.zst");
+    p.write_binary(&zstd::encode_all(TEXT.as_bytes(), 0).unwrap())
+        .unwrap();
+    let mut file = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .open_compressed(Some(Compression::Zstd))
+        .unwrap();
+    let mut s = String::new();
+    file.read_to_string(&mut s).unwrap();
+    assert_eq!(s, TEXT);
+    file.write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt.zst", "file.txt.zst~"]);
+    let zst = std::fs::read(&p).unwrap();
+    assert_eq!(zstd::decode_all(&zst[..]).unwrap(), SWAPPED_TEXT.as_bytes());
+    let zst = std::fs::read(tmpdir.child("file.txt.zst~")).unwrap();
+    assert_eq!(zstd::decode_all(&zst[..]).unwrap(), TEXT.as_bytes());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn open_compressed_create_by_extension() {
+    use super::Compression;
+    use std::io::Read;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("new.txt.gz");
+    let mut file = InPlace::new(&p).create(true).open_compressed(None).unwrap();
+    assert_eq!(file.compression(), Compression::Gzip);
+    let mut s = String::new();
+    file.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "");
+    file.write_all(TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    let gz = std::fs::File::open(&p).unwrap();
+    flate2::read::GzDecoder::new(gz)
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, TEXT);
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[test]
+fn open_compressed_unknown() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let e = InPlace::new(&p).open_compressed(None).unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::UnknownCompression);
+    assert!(e.as_io_error().is_none());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn open_compressed_discard() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt.gz");
+    let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    enc.write_all(TEXT.as_bytes()).unwrap();
+    let data = enc.finish().unwrap();
+    p.write_binary(&data).unwrap();
+    let mut file = InPlace::new(&p).open_compressed(None).unwrap();
+    file.write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.discard().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt.gz"]);
+    assert_eq!(std::fs::read(&p).unwrap(), data);
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let other = tmpdir.child("other.txt");
+    p.write_str(TEXT).unwrap();
+    other.write_str(TEXT).unwrap();
+    let store = tmpdir.child("store");
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Store(store.to_path_buf()));
+    for text in [SWAPPED_TEXT, "Third version\n"] {
+        let file = inp.open().unwrap();
+        file.writer().write_all(text.as_bytes()).unwrap();
+        file.save().unwrap();
+    }
+    let file = InPlace::new(&other)
+        .backup(Backup::Store(store.to_path_buf()))
+        .open()
+        .unwrap();
+    file.writer().write_all(b"Other\n").unwrap();
+    file.save().unwrap();
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["file.txt", "other.txt", "store"]
+    );
+    p.assert("Third version\n");
+    other.assert("Other\n");
+    let versions = inp.list_versions().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(std::fs::read_to_string(versions[0].path()).unwrap(), TEXT);
+    assert_eq!(
+        std::fs::read_to_string(versions[1].path()).unwrap(),
+        SWAPPED_TEXT
+    );
+    assert!(versions[0].timestamp() <= versions[1].timestamp());
+    // Both originals of `other.txt` and `file.txt` were identical and so are
+    // only stored once:
+    assert_eq!(listdir(&store.child("objects")).unwrap().len(), 2);
+    inp.restore_version(versions[0].id()).unwrap();
+    p.assert(TEXT);
+    let versions = inp.list_versions().unwrap();
+    assert_eq!(versions.len(), 3);
+    assert_eq!(
+        std::fs::read_to_string(versions[2].path()).unwrap(),
+        "Third version\n"
+    );
+    assert_eq!(listdir(&store.child("objects")).unwrap().len(), 3);
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_restore_latest() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Store(tmpdir.child("store").to_path_buf()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    let restored = inp.restore().unwrap();
+    assert_eq!(std::fs::read_to_string(restored).unwrap(), TEXT);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_restore_modified() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Store(tmpdir.child("store").to_path_buf()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    p.write_str("Modified\n").unwrap();
+    let e = inp.restore().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Modified);
+    p.assert("Modified\n");
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_malformed_index() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let store = tmpdir.child("store");
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Store(store.to_path_buf()));
+    let file = inp.open().unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    file.save().unwrap();
+    let index = store.child("index");
+    let line = std::fs::read_to_string(&index).unwrap();
+    let (_, rest) = line.split_once('\t').unwrap();
+    let mut fp = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&index)
+        .unwrap();
+    for timestamp in [
+        "18446744073709551615.1000000000",
+        "18446744073709551615.999999999",
+        "1.4294967296",
+        "1.-1",
+        "12345",
+    ] {
+        write!(fp, "{timestamp}\t{rest}").unwrap();
+    }
+    drop(fp);
+    let versions = inp.list_versions().unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(std::fs::read_to_string(versions[0].path()).unwrap(), TEXT);
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_unknown_version() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mut inp = InPlace::new(&p);
+    inp.backup(Backup::Store(tmpdir.child("store").to_path_buf()));
+    assert!(inp.list_versions().unwrap().is_empty());
+    let e = inp.restore_version("0123abcd").unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoBackup);
+    let e = InPlace::new(&p).list_versions().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::NoBackup);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn store_revert() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let file = InPlace::new(&p)
+        .backup(Backup::Store(tmpdir.child("store").to_path_buf()))
+        .open()
+        .unwrap();
+    file.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    let saved = file.save_revertible().unwrap();
+    let blob = saved.backup_path().unwrap().to_path_buf();
+    p.assert(SWAPPED_TEXT);
+    saved.revert().unwrap();
+    p.assert(TEXT);
+    assert!(blob.exists());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "store"]);
+}
+
+#[test]
+fn normalize_writer() {
+    use super::{LineEnding, NormalizeWriter, TrailingNewlines};
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str("foo  \r\nbar\t\r\n\r\n\r\n").unwrap();
+    let inp = InPlace::new(&p).open().unwrap();
+    let mut writer = NormalizeWriter::new(&inp);
+    writer
+        .line_ending(LineEnding::Lf)
+        .strip_trailing_whitespace(true)
+        .trailing_newlines(TrailingNewlines::One);
+    io::copy(&mut inp.reader(), &mut writer).unwrap();
+    writer.finish().unwrap();
+    inp.save().unwrap();
+    p.assert("foo\nbar\n");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn replace_all_captures() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str("name = foo\nname = bar\n").unwrap();
+    let re = regex::bytes::Regex::new(r"name = (\w+)").unwrap();
+    let n = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .replace_all(&re, "${1}_name = $1")
+        .unwrap();
+    assert_eq!(n, 2);
+    p.assert("foo_name = foo\nbar_name = bar\n");
+    tmpdir.child("file.txt~").assert("name = foo\nname = bar\n");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn replace_all_multiline_whole_file() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str("foo\nbar\nfoo\nbaz\n").unwrap();
+    let re = regex::bytes::Regex::new(r"foo\n(\w+)").unwrap();
+    let n = InPlace::new(&p).replace_all(&re, "$1").unwrap();
+    assert_eq!(n, 2);
+    p.assert("bar\nbaz\n");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn replace_all_line_wise_limit() {
+    use super::ReplaceOptions;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str("a a\r\na\nb\na").unwrap();
+    let re = regex::bytes::Regex::new(r"\ba$").unwrap();
+    let n = InPlace::new(&p)
+        .replace_all_with(&re, "x", ReplaceOptions::new().line_wise(true).limit(2))
+        .unwrap();
+    assert_eq!(n, 2);
+    p.assert("a x\r\nx\nb\na");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn replace_all_no_matches() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mtime = p.metadata().unwrap().modified().unwrap();
+    let re = regex::bytes::Regex::new(r"jabber\w+").unwrap();
+    let n = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .replace_all(&re, "bandersnatch")
+        .unwrap();
+    assert_eq!(n, 0);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    assert_eq!(p.metadata().unwrap().modified().unwrap(), mtime);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn replace_all_unchanged() {
+    use super::ReplaceOptions;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let mtime = p.metadata().unwrap().modified().unwrap();
+    let re = regex::bytes::Regex::new(r"(\w+)").unwrap();
+    for line_wise in [false, true] {
+        let n = InPlace::new(&p)
+            .backup(Backup::Append("~".into()))
+            .replace_all_with(&re, "$1", ReplaceOptions::new().line_wise(line_wise))
+            .unwrap();
+        assert!(n > 0);
+        assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+        assert_eq!(p.metadata().unwrap().modified().unwrap(), mtime);
+        p.assert(TEXT);
+    }
+}
+
+#[cfg(feature = "sed")]
+#[test]
+fn sed() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let script = super::SedScript::parse("1,2d;s/^\t//;$a The End").unwrap();
+    InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .sed(&script)
+        .unwrap();
+    p.assert(concat!(
+        "All mimsy were the borogoves,\n",
+        "And the mome raths outgrabe.\n",
+        "The End\n",
+    ));
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn apply_patch() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let patch = super::Patch::parse(concat!(
+        "--- a/file.txt\n",
+        "+++ b/file.txt\n",
+        "@@ -2,3 +2,3 @@\n",
+        " \tDid gyre and gimble in the wabe;\n",
+        "-All mimsy were the borogoves,\n",
+        "+All mimsy were the borogroves,\n",
+        " \tAnd the mome raths outgrabe.\n",
+    ))
+    .unwrap();
+    let applied = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .apply_patch(&patch)
+        .unwrap();
+    assert_eq!(applied.len(), 1);
+    assert_eq!(applied[0].line(), 2);
+    assert_eq!(applied[0].offset(), 0);
+    p.assert(concat!(
+        "'Twas brillig, and the slithy toves\n",
+        "\tDid gyre and gimble in the wabe;\n",
+        "All mimsy were the borogroves,\n",
+        "\tAnd the mome raths outgrabe.\n",
+    ));
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn apply_patch_failed() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let patch = super::Patch::parse(concat!(
+        "@@ -1 +1 @@\n",
+        "-'Twas brillig, and the slithy toves\n",
+        "+'Twas brillig, and the slimy toves\n",
+        "@@ -3 +3 @@\n",
+        "-All flimsy were the borogoves,\n",
+        "+All mimsy were the borogroves,\n",
+    ))
+    .unwrap();
+    let r = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .apply_patch(&patch);
+    let e = r.unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::PatchFailed);
+    let failed = e.failed_hunks().unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].number(), 2);
+    assert_eq!(failed[0].line(), 3);
+    assert_eq!(e.to_string(), "patch does not apply");
+    assert_eq!(
+        std::error::Error::source(&e).unwrap().to_string(),
+        "failed hunks: #2 (line 3)"
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "merge")]
+static MODIFIED_TEXT: &str = concat!(
+    "'Twas brillig, and the slithy toves\n",
+    "\tDid gyre and gimble in the wabe;\n",
+    "All mimsy were the borogoves,\n",
+    "\tAnd the mome wraths outgrabe.\n",
+);
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_merge() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .on_conflict(OnConflict::Merge)
+        .open()
+        .unwrap();
+    let reader = BufReader::new(inp.reader());
+    let mut writer = inp.writer();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.unwrap();
+        if i == 0 {
+            writeln!(writer, "{}", swapcase(&line)).unwrap();
+        } else {
+            writeln!(writer, "{line}").unwrap();
+        }
+    }
+    p.write_str(MODIFIED_TEXT).unwrap();
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(concat!(
+        "'tWAS BRILLIG, AND THE SLITHY TOVES\n",
+        "\tDid gyre and gimble in the wabe;\n",
+        "All mimsy were the borogoves,\n",
+        "\tAnd the mome wraths outgrabe.\n",
+    ));
+    tmpdir.child("file.txt~").assert(MODIFIED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_merge_unmodified() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .on_conflict(OnConflict::Merge)
+        .open()
+        .unwrap();
+    let reader = BufReader::new(inp.reader());
+    let mut writer = inp.writer();
+    for line in reader.lines() {
+        writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+    }
+    inp.save().unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_fail() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .on_conflict(OnConflict::Fail)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    p.write_str(MODIFIED_TEXT).unwrap();
+    let e = inp.save().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Conflict);
+    assert_eq!(e.to_string(), "file was modified while being edited");
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(MODIFIED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_fail_same_length() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .on_conflict(OnConflict::Fail)
+        .open()
+        .unwrap();
+    inp.writer().write_all(b"Replaced\n").unwrap();
+    p.write_str(SWAPPED_TEXT).unwrap();
+    let e = inp.save().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::Conflict);
+    p.assert(SWAPPED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_fail_unmodified() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .on_conflict(OnConflict::Fail)
+        .open()
+        .unwrap();
+    let reader = BufReader::new(inp.reader());
+    let mut writer = inp.writer();
+    for line in reader.lines() {
+        writeln!(writer, "{}", swapcase(&line.unwrap())).unwrap();
+    }
+    inp.save().unwrap();
+    p.assert(SWAPPED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_merge_conflict() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .on_conflict(OnConflict::Merge)
+        .open()
+        .unwrap();
+    inp.writer().write_all(SWAPPED_TEXT.as_bytes()).unwrap();
+    p.write_str(MODIFIED_TEXT).unwrap();
+    let e = inp.save().unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::MergeConflict);
+    assert_eq!(
+        std::error::Error::source(&e).unwrap().to_string(),
+        "conflicting changes at lines 1-4"
+    );
+    let conflicts = e.merge_conflicts().unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].line(), 1);
+    assert_eq!(conflicts[0].line_count(), 4);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(MODIFIED_TEXT);
+}
+
+#[cfg(feature = "merge")]
+#[test]
+fn on_conflict_merge_with_markers() {
+    use super::OnConflict;
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let inp = InPlace::new(&p)
+        .on_conflict(OnConflict::MergeWithMarkers)
+        .open()
+        .unwrap();
+    let reader = BufReader::new(inp.reader());
+    let mut writer = inp.writer();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.unwrap();
+        if i == 3 {
+            writeln!(writer, "\tAnd the mome maths outgrabe.").unwrap();
+        } else {
+            writeln!(writer, "{line}").unwrap();
+        }
+    }
+    p.write_str(MODIFIED_TEXT).unwrap();
+    inp.save().unwrap();
+    p.assert(concat!(
+        "'Twas brillig, and the slithy toves\n",
+        "\tDid gyre and gimble in the wabe;\n",
+        "All mimsy were the borogoves,\n",
+        "<<<<<<< ours\n",
+        "\tAnd the mome maths outgrabe.\n",
+        "=======\n",
+        "\tAnd the mome wraths outgrabe.\n",
+        ">>>>>>> theirs\n",
+    ));
+}
+
+#[cfg(feature = "toml")]
+static TOML_TEXT: &str = concat!(
+    "# Package metadata\n",
+    "[package]\n",
+    "name = \"jabberwocky\"   # the name\n",
+    "version = \"0.1.0\"\n",
+    "\n",
+    "[dependencies]\n",
+    "vorpal = { version = \"1.0\", features = [\"snicker-snack\"] }\n",
+);
+
+#[cfg(feature = "toml")]
+#[test]
+fn edit_toml() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("Cargo.toml");
+    p.write_str(TOML_TEXT).unwrap();
+    let old = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_toml(|doc| {
+            let old = doc["package"]["version"].as_str().map(String::from);
+            doc["package"]["version"] = toml_edit::value("0.2.0");
+            doc["dependencies"]["tumtum"] = toml_edit::value("2.0");
+            old
+        })
+        .unwrap();
+    assert_eq!(old.as_deref(), Some("0.1.0"));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["Cargo.toml", "Cargo.toml~"]);
+    p.assert(concat!(
+        "# Package metadata\n",
+        "[package]\n",
+        "name = \"jabberwocky\"   # the name\n",
+        "version = \"0.2.0\"\n",
+        "\n",
+        "[dependencies]\n",
+        "vorpal = { version = \"1.0\", features = [\"snicker-snack\"] }\n",
+        "tumtum = \"2.0\"\n",
+    ));
+    tmpdir.child("Cargo.toml~").assert(TOML_TEXT);
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn edit_toml_unchanged() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("Cargo.toml");
+    p.write_str(TOML_TEXT).unwrap();
+    let mtime = p.metadata().unwrap().modified().unwrap();
+    let name = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_toml(|doc| doc["package"]["name"].as_str().map(String::from))
+        .unwrap();
+    assert_eq!(name.as_deref(), Some("jabberwocky"));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["Cargo.toml"]);
+    assert_eq!(p.metadata().unwrap().modified().unwrap(), mtime);
+    p.assert(TOML_TEXT);
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn edit_toml_create() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("config.toml");
+    InPlace::new(&p)
+        .create(true)
+        .edit_toml(|doc| doc["name"] = toml_edit::value("Jabberwock"))
+        .unwrap();
+    p.assert("name = \"Jabberwock\"\n");
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn edit_toml_invalid() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("Cargo.toml");
+    p.write_str("[package\nname = \"jabberwocky\"\n").unwrap();
+    let mut called = false;
+    let e = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_toml(|_| called = true)
+        .unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::ParseToml);
+    assert_eq!(e.to_string(), "failed to parse file as TOML");
+    assert!(std::error::Error::source(&e).is_some());
+    assert!(!called);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["Cargo.toml"]);
+    p.assert("[package\nname = \"jabberwocky\"\n");
+}
+
+#[cfg(feature = "json")]
+static JSON_TEXT: &str = concat!(
+    "{\n",
+    "    \"name\": \"jabberwocky\",\n",
+    "    \"version\": \"0.1.0\",\n",
+    "    \"dependencies\": {\n",
+    "        \"vorpal\": \"^1.0\"\n",
+    "    }\n",
+    "}\n",
+);
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("package.json");
+    p.write_str(JSON_TEXT).unwrap();
+    let old = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_json(|value| {
+            let old = value["version"].as_str().map(String::from);
+            value["version"] = "0.2.0".into();
+            value["dependencies"]["tumtum"] = "^2.0".into();
+            old
+        })
+        .unwrap();
+    assert_eq!(old.as_deref(), Some("0.1.0"));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["package.json", "package.json~"]);
+    p.assert(concat!(
+        "{\n",
+        "    \"name\": \"jabberwocky\",\n",
+        "    \"version\": \"0.2.0\",\n",
+        "    \"dependencies\": {\n",
+        "        \"vorpal\": \"^1.0\",\n",
+        "        \"tumtum\": \"^2.0\"\n",
+        "    }\n",
+        "}\n",
+    ));
+    tmpdir.child("package.json~").assert(JSON_TEXT);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json_compact() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("data.json");
+    p.write_str("{\"b\":[1,2],\"a\":null}").unwrap();
+    InPlace::new(&p)
+        .edit_json(|value| value["b"][0] = 3.into())
+        .unwrap();
+    p.assert("{\"b\":[3,2],\"a\":null}");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json_unchanged() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("package.json");
+    // Not in a style that `edit_json()` would write:
+    let text = "{ \"name\" : \"jabberwocky\",\n  \"version\" : \"0.1.0\" }";
+    p.write_str(text).unwrap();
+    let mtime = p.metadata().unwrap().modified().unwrap();
+    InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_json(|value| value["version"] = "0.1.0".into())
+        .unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["package.json"]);
+    assert_eq!(p.metadata().unwrap().modified().unwrap(), mtime);
+    p.assert(text);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json_reorder() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("data.json");
+    p.write_str("{\"b\": 1, \"a\": 2}\n").unwrap();
+    InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_json(|value| {
+            if let Some(obj) = value.as_object_mut() {
+                obj.sort_keys();
+            }
+        })
+        .unwrap();
+    assert_eq!(listdir(&tmpdir).unwrap(), ["data.json", "data.json~"]);
+    p.assert("{\"a\": 2, \"b\": 1}\n");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json_numbers() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("data.json");
+    p.write_str("{\"exp\": 1E3, \"float\": 1.50, \"list\": [1.0, 2.0], \"n\": 1.0}\n")
+        .unwrap();
+    InPlace::new(&p)
+        .edit_json(|value| {
+            value["n"] = 2.5.into();
+            value["list"][0] = 1.into();
+            value["new"] = 1e3.into();
+        })
+        .unwrap();
+    p.assert("{\"exp\": 1E3, \"float\": 1.50, \"list\": [1, 2.0], \"n\": 2.5, \"new\": 1000.0}\n");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn edit_json_invalid() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("package.json");
+    p.write_str("{\"name\": \"jabberwocky\",}\n").unwrap();
+    let mut called = false;
+    let e = InPlace::new(&p)
+        .backup(Backup::Append("~".into()))
+        .edit_json(|_| called = true)
+        .unwrap_err();
+    assert_eq!(e.kind(), InPlaceErrorKind::ParseJson);
+    assert_eq!(e.to_string(), "failed to parse file as JSON");
+    assert!(std::error::Error::source(&e).is_some());
+    assert!(!called);
+    assert_eq!(listdir(&tmpdir).unwrap(), ["package.json"]);
+    p.assert("{\"name\": \"jabberwocky\",}\n");
+}
@@ -0,0 +1,231 @@
+use crate::{InPlace, InPlaceError, InPlaceFile, Output, SavedFile};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A text file that is currently being edited in-place, with its contents
+/// decoded to UTF-8 and new text re-encoded on writing.
+///
+/// A `TextFile` instance can be obtained via [`InPlace::open_text()`].
+///
+/// The decoded contents of the edited file are available via
+/// [`TextFile::text()`], and new contents are written with
+/// [`TextFile::write_str()`].  In order to update the edited file with the
+/// written text, [`TextFile::save()`] must be called once writing is
+/// complete.  Alternatively, calling [`TextFile::discard()`] will discard all
+/// written text and leave the edited file unmodified.
+///
+/// Dropping a `TextFile` without calling `save()` has the same effect as
+/// calling `discard()`, except that any errors are ignored.
+#[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+#[derive(Debug)]
+pub struct TextFile {
+    text: String,
+    encoding: &'static Encoding,
+    had_bom: bool,
+    output_encoding: &'static Encoding,
+    bom_pending: AtomicBool,
+    output: Output,
+}
+
+impl InPlace {
+    /// Open the edited file for in-place editing as with [`InPlace::open()`],
+    /// reading its entire contents and decoding them to UTF-8.
+    ///
+    /// The encoding of the contents is the one set with
+    /// [`InPlace::encoding()`], if any; otherwise, it is detected from a
+    /// byte order mark (BOM) if present, falling back to UTF-8.  Legacy
+    /// encodings such as windows-1252 are never detected and must be set
+    /// explicitly.  A BOM for the encoding is stripped from the decoded text.
+    /// A nonexistent edited file is treated as empty.
+    ///
+    /// Text written with [`TextFile::write_str()`] is encoded in the encoding
+    /// set with [`InPlace::output_encoding()`] (by default, the encoding of
+    /// the contents) and is preceded by a BOM as determined by
+    /// [`InPlace::write_bom()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Read`] if reading the
+    /// edited file fails.  Returns an error of kind
+    /// [`InPlaceErrorKind::Decode`] if the contents are malformed in their
+    /// encoding.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::Decode`]: crate::InPlaceErrorKind::Decode
+    pub fn open_text(&self) -> Result<TextFile, InPlaceError> {
+        let InPlaceFile { reader, output, .. } = self.open()?;
+        let mut bytes = Vec::new();
+        (&reader)
+            .read_to_end(&mut bytes)
+            .map_err(InPlaceError::read)?;
+        drop(reader);
+        let bom = Encoding::for_bom(&bytes);
+        let (encoding, bom_len) = match (self.encoding, bom) {
+            (Some(enc), Some((bom_enc, len))) if enc == bom_enc => (enc, len),
+            (Some(enc), _) => (enc, 0),
+            (None, Some((enc, len))) => (enc, len),
+            (None, None) => (UTF_8, 0),
+        };
+        let text = encoding
+            .decode_without_bom_handling_and_without_replacement(&bytes[bom_len..])
+            .ok_or_else(|| InPlaceError::decode(encoding))?
+            .into_owned();
+        let had_bom = bom_len > 0;
+        Ok(TextFile {
+            text,
+            encoding,
+            had_bom,
+            output_encoding: self.output_encoding.unwrap_or(encoding),
+            bom_pending: AtomicBool::new(self.write_bom.unwrap_or(had_bom)),
+            output,
+        })
+    }
+}
+
+impl TextFile {
+    /// The decoded contents of the edited file, without any BOM
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Consume the `TextFile`'s copy of the decoded contents of the edited
+    /// file, leaving an empty string in its place
+    pub fn take_text(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    /// The encoding of the edited file's contents
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Whether the edited file began with a BOM
+    pub fn had_bom(&self) -> bool {
+        self.had_bom
+    }
+
+    /// The encoding in which written text is saved
+    pub fn output_encoding(&self) -> &'static Encoding {
+        self.output_encoding
+    }
+
+    /// The path to the edited file.  If `follow_symlinks` was set to `true`,
+    /// this will be a canonical path; otherwise, the path is only guaranteed
+    /// to be absolute.
+    pub fn path(&self) -> &Path {
+        self.output.path()
+    }
+
+    /// The path to the temporary file that the encoded output is written to.
+    /// This is an absolute path in the same directory as the edited file.
+    pub fn temp_path(&self) -> &Path {
+        self.output.temp_path()
+    }
+
+    /// The path, if any, where the edited file will be backed up once
+    /// [`TextFile::save()`] is called.  This is an absolute path.
+    pub fn backup_path(&self) -> Option<&Path> {
+        self.output.backup_path()
+    }
+
+    /// Encode `s` in the output encoding and write it to the temporary file.
+    /// If a BOM is to be written, it is written before the first text.
+    ///
+    /// # Errors
+    ///
+    /// Fails if writing fails.  Fails with an error of kind
+    /// [`io::ErrorKind::InvalidData`] if `s` contains characters that cannot
+    /// be represented in the output encoding, in which case nothing is
+    /// written.
+    pub fn write_str(&self, s: &str) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(s.len() + 3);
+        let with_bom = self.bom_pending.swap(false, Ordering::Relaxed);
+        if with_bom {
+            buf.extend_from_slice(bom(self.output_encoding));
+        }
+        if let Err(e) = encode(self.output_encoding, s, &mut buf) {
+            // Leave the BOM for the next write
+            self.bom_pending.store(with_bom, Ordering::Relaxed);
+            return Err(e);
+        }
+        self.output.writer().write_all(&buf)
+    }
+
+    /// Save the written text as with [`InPlaceFile::save()`].
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn save(self) -> Result<(), InPlaceError> {
+        self.output.save()
+    }
+
+    /// Save the written text as with [`InPlaceFile::save_revertible()`].
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceFile::save_revertible()`]: crate::InPlaceFile::save_revertible
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn save_revertible(self) -> Result<SavedFile, InPlaceError> {
+        self.output.save_revertible()
+    }
+
+    /// Close all filehandles and do not update or back up the edited file.
+    ///
+    /// # Errors
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// operations that this method can fail on.
+    ///
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    pub fn discard(self) -> Result<(), InPlaceError> {
+        self.output.discard()
+    }
+}
+
+/// The byte order mark for `encoding`, or an empty slice if it does not have
+/// one
+fn bom(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_8 {
+        b"\xEF\xBB\xBF"
+    } else if encoding == UTF_16LE {
+        b"\xFF\xFE"
+    } else if encoding == UTF_16BE {
+        b"\xFE\xFF"
+    } else {
+        b""
+    }
+}
+
+/// Append `s` encoded in `encoding` to `buf`.  `encoding_rs` does not encode
+/// to UTF-16, so that is done here.
+fn encode(encoding: &'static Encoding, s: &str, buf: &mut Vec<u8>) -> io::Result<()> {
+    if encoding == UTF_16LE {
+        buf.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+    } else if encoding == UTF_16BE {
+        buf.extend(s.encode_utf16().flat_map(u16::to_be_bytes));
+    } else {
+        let (bytes, _, had_errors) = encoding.encode(s);
+        if had_errors {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("text cannot be represented in {}", encoding.name()),
+            ));
+        }
+        buf.extend_from_slice(&bytes);
+    }
+    Ok(())
+}
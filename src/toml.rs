@@ -0,0 +1,64 @@
+use crate::{InPlace, InPlaceError};
+use std::io::{Read, Write};
+use toml_edit::DocumentMut;
+
+impl InPlace {
+    /// Edit the edited file as a TOML document, preserving its comments,
+    /// whitespace, and formatting.
+    ///
+    /// The edited file is opened with [`InPlace::open()`] and its entire
+    /// contents are parsed into a [`toml_edit::DocumentMut`], which is then
+    /// passed to `f` for modification.  A nonexistent edited file (if
+    /// [`InPlace::create()`] is set) is treated as an empty document.  The
+    /// return value of `f` is returned by this method.
+    ///
+    /// Once `f` returns, the document is serialized, and, if the result
+    /// differs from the original contents, the file is saved with
+    /// [`InPlaceFile::save()`].  Otherwise, the file is discarded with
+    /// [`InPlaceFile::discard()`], so that files left unchanged by `f` are
+    /// left untouched, and no backup is made.  Parts of the document that
+    /// were not modified by `f` are written back exactly as they were read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`InPlaceErrorKind::Read`] if reading the
+    /// edited file fails or its contents are not valid UTF-8, of kind
+    /// [`InPlaceErrorKind::ParseToml`] if the contents are not valid TOML,
+    /// or of kind [`InPlaceErrorKind::Write`] if writing to the temporary
+    /// file fails.  If parsing fails, `f` is not called.
+    ///
+    /// See the documentation for the variants of [`InPlaceErrorKind`] for the
+    /// other operations & checks that this method can fail on.
+    ///
+    /// [`InPlaceFile::save()`]: crate::InPlaceFile::save
+    /// [`InPlaceFile::discard()`]: crate::InPlaceFile::discard
+    /// [`InPlaceErrorKind`]: crate::InPlaceErrorKind
+    /// [`InPlaceErrorKind::Read`]: crate::InPlaceErrorKind::Read
+    /// [`InPlaceErrorKind::ParseToml`]: crate::InPlaceErrorKind::ParseToml
+    /// [`InPlaceErrorKind::Write`]: crate::InPlaceErrorKind::Write
+    #[cfg_attr(docsrs, doc(cfg(feature = "toml")))]
+    pub fn edit_toml<F, T>(&self, f: F) -> Result<T, InPlaceError>
+    where
+        F: FnOnce(&mut DocumentMut) -> T,
+    {
+        let file = self.open()?;
+        let mut content = String::new();
+        file.reader()
+            .read_to_string(&mut content)
+            .map_err(InPlaceError::read)?;
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .map_err(InPlaceError::parse_toml)?;
+        let r = f(&mut doc);
+        let out = doc.to_string();
+        if out == content {
+            file.discard()?;
+        } else {
+            file.writer()
+                .write_all(out.as_bytes())
+                .map_err(InPlaceError::write)?;
+            file.save()?;
+        }
+        Ok(r)
+    }
+}
@@ -0,0 +1,457 @@
+#![cfg(unix)]
+use assert_fs::fixture::TempDir;
+use assert_fs::prelude::*;
+use std::fs::read_dir;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+static TEXT: &str = concat!(
+    "'Twas brillig, and the slithy toves\n",
+    "\tDid gyre and gimble in the wabe;\n",
+    "All mimsy were the borogoves,\n",
+    "\tAnd the mome raths outgrabe.\n",
+);
+
+static UPPER_TEXT: &str = concat!(
+    "'TWAS BRILLIG, AND THE SLITHY TOVES\n",
+    "\tDID GYRE AND GIMBLE IN THE WABE;\n",
+    "ALL MIMSY WERE THE BOROGOVES,\n",
+    "\tAND THE MOME RATHS OUTGRABE.\n",
+);
+
+fn listdir(dirpath: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in read_dir(dirpath)? {
+        files.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn in_place(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_in-place"))
+        .args(args)
+        .output()
+        .expect("failed to run in-place")
+}
+
+fn in_place_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_in-place"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run in-place");
+    let mut stdin = child.stdin.take().expect("stdin should be piped");
+    stdin
+        .write_all(input.as_bytes())
+        .expect("failed to write to in-place");
+    drop(stdin);
+    child
+        .wait_with_output()
+        .expect("failed to wait on in-place")
+}
+
+#[test]
+fn filter() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[p.to_str().unwrap(), "--", "tr", "a-z", "A-Z"]);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(UPPER_TEXT);
+}
+
+#[test]
+fn filter_no_separator() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[p.to_str().unwrap(), "tr", "a-z", "A-Z"]);
+    assert!(out.status.success());
+    p.assert(UPPER_TEXT);
+}
+
+#[test]
+fn filter_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[
+        "--backup",
+        ".bak",
+        p.to_str().unwrap(),
+        "--",
+        "tr",
+        "a-z",
+        "A-Z",
+    ]);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt.bak"]);
+    p.assert(UPPER_TEXT);
+    tmpdir.child("file.txt.bak").assert(TEXT);
+}
+
+#[test]
+fn filter_backup_path() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let bkp = tmpdir.child("backup.txt");
+    let out = in_place(&[
+        &format!("--backup-path={}", bkp.display()),
+        p.to_str().unwrap(),
+        "--",
+        "tr",
+        "a-z",
+        "A-Z",
+    ]);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["backup.txt", "file.txt"]);
+    p.assert(UPPER_TEXT);
+    bkp.assert(TEXT);
+}
+
+#[test]
+fn filter_numbered() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&["--numbered", p.to_str().unwrap(), "--", "tr", "a-z", "A-Z"]);
+    assert!(out.status.success());
+    let out = in_place(&["--numbered", p.to_str().unwrap(), "--", "tr", "A-Z", "a-z"]);
+    assert!(out.status.success());
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["file.txt", "file.txt.~1~", "file.txt.~2~"]
+    );
+    p.assert(TEXT.to_lowercase());
+    tmpdir.child("file.txt.~1~").assert(TEXT);
+    tmpdir.child("file.txt.~2~").assert(UPPER_TEXT);
+}
+
+#[test]
+fn filter_numbered_existing() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    for (name, content) in [
+        ("file.txt.~2~", "Version 2\n"),
+        ("file.txt.~10~", "Version 10\n"),
+        ("file.txt.~x~", "Not a number\n"),
+        ("other.txt.~20~", "Other\n"),
+    ] {
+        tmpdir.child(name).write_str(content).unwrap();
+    }
+    let out = in_place(&["--numbered", p.to_str().unwrap(), "--", "tr", "a-z", "A-Z"]);
+    assert!(out.status.success());
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        [
+            "file.txt",
+            "file.txt.~10~",
+            "file.txt.~11~",
+            "file.txt.~2~",
+            "file.txt.~x~",
+            "other.txt.~20~"
+        ]
+    );
+    p.assert(UPPER_TEXT);
+    tmpdir.child("file.txt.~11~").assert(TEXT);
+}
+
+#[test]
+fn filter_command_fails() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[
+        "--backup",
+        "~",
+        p.to_str().unwrap(),
+        "--",
+        "sh",
+        "-c",
+        "tr a-z A-Z; exit 3",
+    ]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn filter_no_such_command() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[p.to_str().unwrap(), "--", "this-command-does-not-exist"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: failed to run this-command-does-not-exist: "),
+        "stderr = {stderr:?}"
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[test]
+fn filter_nonexistent() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let out = in_place(&[p.to_str().unwrap(), "--", "cat"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("failed to canonicalize path"),
+        "stderr = {stderr:?}"
+    );
+    assert!(listdir(&tmpdir).unwrap().is_empty());
+}
+
+#[test]
+fn no_command() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[p.to_str().unwrap(), "--"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: no command specified\n"),
+        "stderr = {stderr:?}"
+    );
+    p.assert(TEXT);
+}
+
+#[test]
+fn unknown_option() {
+    let out = in_place(&["--frobnicate", "file.txt", "--", "cat"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: unrecognized option: --frobnicate\n"),
+        "stderr = {stderr:?}"
+    );
+}
+
+#[test]
+fn sponge() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place_stdin(&["--sponge", p.to_str().unwrap()], UPPER_TEXT);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(UPPER_TEXT);
+}
+
+#[test]
+fn sponge_pipeline() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let script = format!(
+        "grep -v mimsy {0} | {1} --sponge {0}",
+        p.display(),
+        env!("CARGO_BIN_EXE_in-place")
+    );
+    let out = Command::new("sh").arg("-c").arg(script).output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(concat!(
+        "'Twas brillig, and the slithy toves\n",
+        "\tDid gyre and gimble in the wabe;\n",
+        "\tAnd the mome raths outgrabe.\n",
+    ));
+}
+
+#[test]
+fn sponge_backup() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place_stdin(&["--backup=~", "--sponge", p.to_str().unwrap()], UPPER_TEXT);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt~"]);
+    p.assert(UPPER_TEXT);
+    tmpdir.child("file.txt~").assert(TEXT);
+}
+
+#[test]
+fn sponge_nonexistent() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let out = in_place_stdin(&["--sponge", p.to_str().unwrap()], UPPER_TEXT);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(UPPER_TEXT);
+}
+
+#[test]
+fn sponge_append() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place_stdin(
+        &[
+            "--sponge",
+            "--append",
+            "--backup",
+            ".bak",
+            p.to_str().unwrap(),
+        ],
+        UPPER_TEXT,
+    );
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt.bak"]);
+    p.assert(format!("{TEXT}{UPPER_TEXT}"));
+    tmpdir.child("file.txt.bak").assert(TEXT);
+}
+
+#[test]
+fn sponge_append_waits_for_input() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str("Old text\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_in-place"))
+        .args(["--sponge", "--append", "--numbered", p.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run in-place");
+    let mut stdin = child.stdin.take().expect("child should have stdin");
+    stdin
+        .write_all(UPPER_TEXT.as_bytes())
+        .expect("failed to write to in-place");
+    // Neither the file's contents nor the backup number may be determined
+    // until end of input:
+    p.write_str(TEXT).unwrap();
+    tmpdir
+        .child("file.txt.~1~")
+        .write_str("Old text\n")
+        .unwrap();
+    drop(stdin);
+    let out = child.wait_with_output().expect("failed to run in-place");
+    assert!(out.status.success());
+    assert_eq!(
+        listdir(&tmpdir).unwrap(),
+        ["file.txt", "file.txt.~1~", "file.txt.~2~"]
+    );
+    p.assert(format!("{TEXT}{UPPER_TEXT}"));
+    tmpdir.child("file.txt.~2~").assert(TEXT);
+}
+
+#[test]
+fn sponge_append_nonexistent() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    let out = in_place_stdin(&["--sponge", "--append", p.to_str().unwrap()], UPPER_TEXT);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(UPPER_TEXT);
+}
+
+#[test]
+fn sponge_with_command() {
+    let out = in_place(&["--sponge", "file.txt", "--", "cat"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: --sponge does not take a command\n"),
+        "stderr = {stderr:?}"
+    );
+}
+
+#[test]
+fn append_without_sponge() {
+    let out = in_place(&["--append", "file.txt", "--", "cat"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: --append requires --sponge\n"),
+        "stderr = {stderr:?}"
+    );
+}
+
+#[cfg(feature = "sed")]
+#[test]
+fn sed() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&[
+        "--backup=.bak",
+        "--sed",
+        "/mimsy/d",
+        "--sed",
+        r"s/(gyre) and (\w+)/\2 and \1/",
+        p.to_str().unwrap(),
+    ]);
+    assert!(out.status.success());
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt", "file.txt.bak"]);
+    p.assert(concat!(
+        "'Twas brillig, and the slithy toves\n",
+        "\tDid gimble and gyre in the wabe;\n",
+        "\tAnd the mome raths outgrabe.\n",
+    ));
+    tmpdir.child("file.txt.bak").assert(TEXT);
+}
+
+#[cfg(feature = "sed")]
+#[test]
+fn sed_quiet() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&["-n", "--sed", "/^\t/p", p.to_str().unwrap()]);
+    assert!(out.status.success());
+    p.assert(concat!(
+        "\tDid gyre and gimble in the wabe;\n",
+        "\tAnd the mome raths outgrabe.\n",
+    ));
+}
+
+#[cfg(feature = "sed")]
+#[test]
+fn sed_invalid_script() {
+    let tmpdir = TempDir::new().unwrap();
+    let p = tmpdir.child("file.txt");
+    p.write_str(TEXT).unwrap();
+    let out = in_place(&["--sed", "s/a/b", p.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: invalid sed script: line 1: "),
+        "stderr = {stderr:?}"
+    );
+    assert_eq!(listdir(&tmpdir).unwrap(), ["file.txt"]);
+    p.assert(TEXT);
+}
+
+#[cfg(feature = "sed")]
+#[test]
+fn sed_with_command() {
+    let out = in_place(&["--sed", "p", "file.txt", "--", "cat"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: --sed does not take a command\n"),
+        "stderr = {stderr:?}"
+    );
+}
+
+#[test]
+fn quiet_without_sed() {
+    let out = in_place(&["-n", "file.txt", "--", "cat"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("in-place: --quiet requires --sed\n"),
+        "stderr = {stderr:?}"
+    );
+}